@@ -1,121 +1,370 @@
+//! A simple ascii coding for id trees, event trees and stamps.
+//!
+//! Id trees are written as `0`, `1` or `(left,right)`, event trees as `n` or
+//! `(n,left,right)` and stamps as `(id,event)`, e.g. `((1,0),(0,1,0))`, with
+//! `@epoch` appended once rebased, e.g. `((1,0),(0,1,0))@2`.
+//! Deltas are written the same way, with `=` for an id or event subtree that
+//! is the same as the base's, e.g. `(=,(1,=,4))`, and `@epoch` only if the
+//! epoch changed.
+//! Whitespace is allowed between tokens, and the whole input must be consumed.
+//! Trees nested deeper than `MAX_DEPTH` are rejected, unless the limit is
+//! changed with `Parser::with_max_depth`.
+
+use std::error;
 use std::fmt;
-use std::num;
 use std::iter::Peekable;
+use std::num;
 use std::str::FromStr;
 
-use super::*;
+use crate::counter::Counter;
+use crate::delta::{EventDelta, StampDelta};
+use crate::{EventTree, IdTree, Stamp, MAX_DEPTH};
 
 impl fmt::Display for IdTree {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            IdTree::Leaf {i} => {
-                write!(f, "{}", if i { 1 } else { 0 })
-            },
-            IdTree::Node {ref left, ref right} => {
-                write!(f, "({},{})", left, right)
-            }
+            IdTree::Leaf { i } => write!(f, "{}", if i { 1 } else { 0 }),
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => write!(f, "({},{})", left, right),
+        }
+    }
+}
+
+impl<N: Counter> fmt::Display for EventTree<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventTree::Leaf { n } => write!(f, "{}", n),
+            EventTree::Node {
+                n,
+                ref left,
+                ref right,
+            } => write!(f, "({},{},{})", n, left, right),
+        }
+    }
+}
+
+impl<N: Counter> fmt::Display for Stamp<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({},{})", self.i, self.e)?;
+        if self.epoch != 0 {
+            write!(f, "@{}", self.epoch)?;
+        }
+        Ok(())
+    }
+}
+
+impl<N: Counter> fmt::Display for EventDelta<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventDelta::Same => write!(f, "="),
+            EventDelta::Leaf { n } => write!(f, "{}", n),
+            EventDelta::Node {
+                n,
+                ref left,
+                ref right,
+            } => write!(f, "({},{},{})", n, left, right),
         }
     }
 }
 
+impl<N: Counter> fmt::Display for StampDelta<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id() {
+            Some(i) => write!(f, "({},{})", i, self.events())?,
+            None => write!(f, "(=,{})", self.events())?,
+        }
+        if let Some(epoch) = self.epoch() {
+            write!(f, "@{}", epoch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors produced while parsing the ascii coding.
+///
+/// Every variant carries the zero-based character position at which
+/// the problem was detected.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseError {
-    EndOfString,
-    Error,
-    UnexpectedChar(char),
-    ParseIntError(num::ParseIntError)
+    EndOfString(usize),
+    UnexpectedChar(char, usize),
+    ParseIntError(num::ParseIntError, usize),
+    TrailingInput(usize),
+    DepthLimitExceeded(usize),
 }
 
-impl From<num::ParseIntError> for ParseError {
-    fn from(e: num::ParseIntError) -> ParseError {
-        ParseError::ParseIntError(e)
+impl ParseError {
+    pub fn position(&self) -> usize {
+        match *self {
+            ParseError::EndOfString(pos) => pos,
+            ParseError::UnexpectedChar(_, pos) => pos,
+            ParseError::ParseIntError(_, pos) => pos,
+            ParseError::TrailingInput(pos) => pos,
+            ParseError::DepthLimitExceeded(pos) => pos,
+        }
     }
 }
 
-pub struct Parser;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::EndOfString(pos) => {
+                write!(f, "unexpected end of input at position {}", pos)
+            }
+            ParseError::UnexpectedChar(c, pos) => {
+                write!(f, "unexpected character {:?} at position {}", c, pos)
+            }
+            ParseError::ParseIntError(ref e, pos) => {
+                write!(f, "invalid number at position {}: {}", pos, e)
+            }
+            ParseError::TrailingInput(pos) => write!(f, "trailing input at position {}", pos),
+            ParseError::DepthLimitExceeded(pos) => {
+                write!(f, "tree exceeds the depth limit at position {}", pos)
+            }
+        }
+    }
+}
 
-impl Parser {
-    pub fn take_number<I>(i: &mut Peekable<I>) -> Result<u32, ParseError> where I: Iterator<Item=char> {
-        let mut s = String::new();
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ParseError::ParseIntError(ref e, _) => Some(e),
+            _ => None,
+        }
+    }
+}
 
-        loop {
-            match i.peek().map(|c| *c) {
-                Some(c) if c >= '0' && c <= '9' => {
-                    i.next();
-                    s.push(c);
-                },
-                _ => {
-                    break;
-                }
+/// Recursive descent parser over a stream of characters.
+///
+/// The parser keeps track of its position so errors can point at the
+/// offending character.
+pub struct Parser<I>
+where
+    I: Iterator<Item = char>,
+{
+    chars: Peekable<I>,
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<I> Parser<I>
+where
+    I: Iterator<Item = char>,
+{
+    pub fn new(chars: I) -> Parser<I> {
+        Parser {
+            chars: chars.peekable(),
+            pos: 0,
+            depth: 0,
+            max_depth: MAX_DEPTH,
+        }
+    }
+
+    /// Rejects trees nested deeper than `max_depth` instead of `MAX_DEPTH`.
+    pub fn with_max_depth(self, max_depth: usize) -> Parser<I> {
+        Parser { max_depth, ..self }
+    }
+
+    /// Zero-based character position of the next character to be consumed.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.peek().copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
             }
+            self.next_char();
         }
-        
-        s.parse::<u32>().map_err(ParseError::from)
     }
 
-    pub fn take_char<I>(i: &mut I, expected: char) -> Result<(), ParseError> where I: Iterator<Item=char> {
-        match i.next() {
-            None => {
-                Err(ParseError::EndOfString)
-            },
-            Some(c) if c == expected => {
-                Ok(())
-            },
-            Some(c) => {
-                Err(ParseError::UnexpectedChar(c))
+    pub fn take_number(&mut self) -> Result<u32, ParseError> {
+        self.take_counter()
+    }
+
+    /// Like `take_number`, for any counter type.
+    pub fn take_counter<N: Counter>(&mut self) -> Result<N, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let mut s = String::new();
+
+        while let Some(c) = self.chars.peek().copied() {
+            if !c.is_ascii_digit() {
+                break;
             }
+            self.next_char();
+            s.push(c);
+        }
+
+        if s.is_empty() {
+            return match self.chars.peek().copied() {
+                Some(c) => Err(ParseError::UnexpectedChar(c, self.pos)),
+                None => Err(ParseError::EndOfString(self.pos)),
+            };
+        }
+
+        s.parse::<N>()
+            .map_err(|e| ParseError::ParseIntError(e, start))
+    }
+
+    pub fn take_char(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        let pos = self.pos;
+        match self.next_char() {
+            None => Err(ParseError::EndOfString(pos)),
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(ParseError::UnexpectedChar(c, pos)),
         }
     }
 
-    pub fn take_id_tree<I>(i: &mut Peekable<I>) -> Result<IdTree, ParseError> where I: Iterator<Item=char> {
-        match i.peek().map(|c| *c) {
+    pub fn take_id_tree(&mut self) -> Result<IdTree, ParseError> {
+        match self.peek_char() {
             Some('(') => {
-                try!(Self::take_char(i, '('));
-                let left = try!(Self::take_id_tree(i));
-                try!(Self::take_char(i, ','));
-                let right = try!(Self::take_id_tree(i));
-                try!(Self::take_char(i, ')'));
+                self.enter_node()?;
+                let left = self.take_id_tree()?;
+                self.take_char(',')?;
+                let right = self.take_id_tree()?;
+                self.leave_node()?;
                 Ok(IdTree::node(Box::new(left), Box::new(right)))
-            },
-            None => {
-                Err(ParseError::EndOfString)
-            },
+            }
+            Some('0') => {
+                self.next_char();
+                Ok(IdTree::zero())
+            }
+            Some('1') => {
+                self.next_char();
+                Ok(IdTree::one())
+            }
+            Some(c) => Err(ParseError::UnexpectedChar(c, self.pos)),
+            None => Err(ParseError::EndOfString(self.pos)),
+        }
+    }
+
+    pub fn take_event_tree<N: Counter>(&mut self) -> Result<EventTree<N>, ParseError> {
+        match self.peek_char() {
+            Some('(') => {
+                self.enter_node()?;
+                let n = self.take_counter()?;
+                self.take_char(',')?;
+                let left = self.take_event_tree()?;
+                self.take_char(',')?;
+                let right = self.take_event_tree()?;
+                self.leave_node()?;
+                Ok(EventTree::new_node(n, Box::new(left), Box::new(right)))
+            }
+            None => Err(ParseError::EndOfString(self.pos)),
             _ => {
-                let n = try!(Self::take_number(i));
-                Ok(IdTree::leaf(n != 0))
+                let n = self.take_counter()?;
+                Ok(EventTree::new_leaf(n))
             }
         }
     }
 
-    pub fn take_event_tree<I>(i: &mut Peekable<I>) -> Result<EventTree, ParseError> where I: Iterator<Item=char> {
-        match i.peek().map(|c| *c) {
+    // consumes the '(' opening a tree node, failing if it nests too deeply
+    fn enter_node(&mut self) -> Result<(), ParseError> {
+        if self.depth == self.max_depth {
+            return Err(ParseError::DepthLimitExceeded(self.pos));
+        }
+        self.take_char('(')?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave_node(&mut self) -> Result<(), ParseError> {
+        self.take_char(')')?;
+        self.depth -= 1;
+        Ok(())
+    }
+
+    pub fn take_stamp<N: Counter>(&mut self) -> Result<Stamp<N>, ParseError> {
+        self.take_char('(')?;
+        let i = self.take_id_tree()?;
+        self.take_char(',')?;
+        let e = self.take_event_tree()?;
+        self.take_char(')')?;
+        let epoch = self.take_epoch()?.unwrap_or(0);
+        Ok(Stamp { i, e, epoch })
+    }
+
+    // the optional `@epoch` suffix of a stamp or delta
+    fn take_epoch(&mut self) -> Result<Option<u32>, ParseError> {
+        if self.peek_char() != Some('@') {
+            return Ok(None);
+        }
+        self.next_char();
+        self.take_number().map(Some)
+    }
+
+    pub fn take_event_delta<N: Counter>(&mut self) -> Result<EventDelta<N>, ParseError> {
+        match self.peek_char() {
+            Some('=') => {
+                self.next_char();
+                Ok(EventDelta::Same)
+            }
             Some('(') => {
-                try!(Self::take_char(i, '('));
-                let n = try!(Self::take_number(i));
-                try!(Self::take_char(i, ','));
-                let left = try!(Self::take_event_tree(i));
-                try!(Self::take_char(i, ','));
-                let right = try!(Self::take_event_tree(i));
-                try!(Self::take_char(i, ')'));
-                Ok(EventTree::node(n, Box::new(left), Box::new(right)))
-            },
-            None => {
-                Err(ParseError::EndOfString)
-            },
+                self.enter_node()?;
+                let n = self.take_counter()?;
+                self.take_char(',')?;
+                let left = self.take_event_delta()?;
+                self.take_char(',')?;
+                let right = self.take_event_delta()?;
+                self.leave_node()?;
+                Ok(EventDelta::Node {
+                    n,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            None => Err(ParseError::EndOfString(self.pos)),
             _ => {
-                let n = try!(Self::take_number(i));
-                Ok(EventTree::leaf(n))
+                let n = self.take_counter()?;
+                Ok(EventDelta::Leaf { n })
             }
         }
     }
 
-    pub fn take_stamp<I>(p: &mut Peekable<I>) -> Result<Stamp, ParseError> where I: Iterator<Item=char> {
-        try!(Self::take_char(p, '('));
-        let i = try!(Self::take_id_tree(p));
-        try!(Self::take_char(p, ','));
-        let e = try!(Self::take_event_tree(p));
-        try!(Self::take_char(p, ')'));
-        Ok(Stamp::new(i, e))
+    pub fn take_stamp_delta<N: Counter>(&mut self) -> Result<StampDelta<N>, ParseError> {
+        self.take_char('(')?;
+        let i = match self.peek_char() {
+            Some('=') => {
+                self.next_char();
+                None
+            }
+            _ => Some(self.take_id_tree()?),
+        };
+        self.take_char(',')?;
+        let e = self.take_event_delta()?;
+        self.take_char(')')?;
+        let delta = StampDelta::new(i, e);
+        Ok(match self.take_epoch()? {
+            Some(epoch) => delta.with_epoch(epoch),
+            None => delta,
+        })
+    }
+
+    /// Succeeds only if nothing but whitespace remains in the input.
+    pub fn finish(&mut self) -> Result<(), ParseError> {
+        match self.peek_char() {
+            None => Ok(()),
+            Some(_) => Err(ParseError::TrailingInput(self.pos)),
+        }
     }
 }
 
@@ -123,92 +372,242 @@ impl FromStr for IdTree {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut p = s.chars().peekable();
-        Parser::take_id_tree(&mut p)
-    } 
+        let mut p = Parser::new(s.chars());
+        let i = p.take_id_tree()?;
+        p.finish()?;
+        Ok(i)
+    }
 }
 
-impl FromStr for EventTree {
+impl<N: Counter> FromStr for EventTree<N> {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut p = s.chars().peekable();
-        Parser::take_event_tree(&mut p)
+        let mut p = Parser::new(s.chars());
+        let e = p.take_event_tree()?;
+        p.finish()?;
+        Ok(e)
     }
 }
 
-impl FromStr for Stamp {
+impl<N: Counter> FromStr for Stamp<N> {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut p = s.chars().peekable();
-        Parser::take_stamp(&mut p)
+        let mut p = Parser::new(s.chars());
+        let stamp = p.take_stamp()?;
+        p.finish()?;
+        Ok(stamp)
     }
 }
 
-impl fmt::Display for EventTree {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            EventTree::Leaf {n} => {
-                write!(f, "{}", n)
-            },
-            EventTree::Node {n, ref left, ref right} => {
-                write!(f, "({},{},{})", n, left, right)
-            }
-        }
+impl<N: Counter> FromStr for EventDelta<N> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut p = Parser::new(s.chars());
+        let e = p.take_event_delta()?;
+        p.finish()?;
+        Ok(e)
     }
 }
 
-impl fmt::Display for Stamp {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({},{})", self.i, self.e)
+impl<N: Counter> FromStr for StampDelta<N> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut p = Parser::new(s.chars());
+        let delta = p.take_stamp_delta()?;
+        p.finish()?;
+        Ok(delta)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use ::{IdTree,EventTree,Stamp};
-    use std::str::FromStr;
     use super::*;
+    use crate::{EventTree, IdTree, IntervalTreeClock, Stamp, MAX_DEPTH};
+    use std::str::FromStr;
 
     #[test]
     fn id_tree_display() {
         assert_eq!("0", format!("{}", IdTree::zero()));
-        assert_eq!("(0,(1,0))", format!("{}", IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero()))))));
+        assert_eq!(
+            "(0,(1,0))",
+            format!(
+                "{}",
+                IdTree::node(
+                    Box::new(IdTree::zero()),
+                    Box::new(IdTree::node(
+                        Box::new(IdTree::one()),
+                        Box::new(IdTree::zero())
+                    ))
+                )
+            )
+        );
     }
 
     #[test]
     fn event_tree_display() {
         assert_eq!("0", format!("{}", EventTree::zero()));
-        assert_eq!("(0,1,(2,1,0))", format!("{}", EventTree::node(0, Box::new(EventTree::leaf(1)), Box::new(EventTree::node(2, Box::new(EventTree::leaf(1)), Box::new(EventTree::zero()))))));
+        assert_eq!(
+            "(0,1,(2,1,0))",
+            format!(
+                "{}",
+                EventTree::node(
+                    0,
+                    Box::new(EventTree::leaf(1)),
+                    Box::new(EventTree::node(
+                        2,
+                        Box::new(EventTree::leaf(1)),
+                        Box::new(EventTree::zero())
+                    ))
+                )
+            )
+        );
     }
 
     #[test]
     fn stamp_display() {
-        let i = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero()))));
-        let e = EventTree::node(0, Box::new(EventTree::leaf(1)), Box::new(EventTree::node(2, Box::new(EventTree::leaf(1)), Box::new(EventTree::zero()))));
+        let i = IdTree::node(
+            Box::new(IdTree::zero()),
+            Box::new(IdTree::node(
+                Box::new(IdTree::one()),
+                Box::new(IdTree::zero()),
+            )),
+        );
+        let e = EventTree::node(
+            0,
+            Box::new(EventTree::leaf(1)),
+            Box::new(EventTree::node(
+                2,
+                Box::new(EventTree::leaf(1)),
+                Box::new(EventTree::zero()),
+            )),
+        );
         let s = Stamp::new(i, e);
         assert_eq!("((0,(1,0)),(0,1,(2,1,0)))", format!("{}", s));
     }
 
     #[test]
     fn test_parser_take_number() {
-        let mut p = "0".chars().peekable();
-        assert_eq!(0, Parser::take_number(&mut p).expect("parse number"));
+        let mut p = Parser::new("0".chars());
+        assert_eq!(0, p.take_number().expect("parse number"));
 
-        let mut p = "1234".chars().peekable();
-        assert_eq!(1234, Parser::take_number(&mut p).expect("parse number"));
+        let mut p = Parser::new("1234".chars());
+        assert_eq!(1234, p.take_number().expect("parse number"));
 
-        let mut p = "1234,(foo)".chars().peekable();
-        assert_eq!(1234, Parser::take_number(&mut p).expect("parse number"));
+        let mut p = Parser::new("1234,(foo)".chars());
+        assert_eq!(1234, p.take_number().expect("parse number"));
+        assert_eq!(4, p.position());
     }
 
     #[test]
     fn test_parser_take_id_tree() {
         assert_eq!(IdTree::zero(), IdTree::from_str("0").expect("parse idtree"));
         assert_eq!(IdTree::one(), IdTree::from_str("1").expect("parse idtree"));
-        assert_eq!(IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero())), IdTree::from_str("(1,0)").expect("parse idtree"));
-        assert_eq!(IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero())), IdTree::from_str("(1,0),foo").expect("parse idtree"));
+        assert_eq!(
+            IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero())),
+            IdTree::from_str("(1,0)").expect("parse idtree")
+        );
+    }
+
+    #[test]
+    fn test_parser_rejects_trailing_input() {
+        assert_eq!(
+            Err(ParseError::TrailingInput(5)),
+            IdTree::from_str("(1,0),foo")
+        );
+        assert_eq!(
+            Err(ParseError::TrailingInput(2)),
+            EventTree::<u32>::from_str("1 2")
+        );
+        assert_eq!(
+            Err(ParseError::TrailingInput(6)),
+            Stamp::<u32>::from_str("(1,0) )")
+        );
+    }
+
+    #[test]
+    fn test_parser_whitespace() {
+        assert_eq!(
+            IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero())),
+            IdTree::from_str(" ( 1 ,\t0 ) \n").expect("parse idtree")
+        );
+        assert_eq!(
+            Stamp::<u32>::from_str("((1,(0,1)),(2,1,(0,0,1)))"),
+            Stamp::from_str(" ( (1, (0, 1)), (2, 1, (0, 0, 1)) ) ")
+        );
+    }
+
+    #[test]
+    fn test_parser_error_positions() {
+        assert_eq!(
+            Err(ParseError::UnexpectedChar('2', 3)),
+            IdTree::from_str("(1,2)")
+        );
+        assert_eq!(Err(ParseError::EndOfString(4)), IdTree::from_str("(1,0"));
+        assert_eq!(
+            Err(ParseError::UnexpectedChar('x', 0)),
+            EventTree::<u32>::from_str("x")
+        );
+        assert_eq!(
+            Err(ParseError::EndOfString(2)),
+            Stamp::<u32>::from_str("  ")
+        );
+
+        let err = EventTree::<u32>::from_str("(99999999999,0,0)").unwrap_err();
+        assert_eq!(1, err.position());
+        let err = EventTree::<u32>::from_str("( 99999999999,0,0)").unwrap_err();
+        assert_eq!(2, err.position());
+    }
+
+    #[test]
+    fn test_parser_depth_limit() {
+        let nested = |depth: usize| "(".repeat(depth) + "1" + &",0)".repeat(depth);
+
+        let i = IdTree::from_str(&nested(MAX_DEPTH)).unwrap();
+        assert_eq!(MAX_DEPTH, i.depth());
+        assert_eq!(
+            Err(ParseError::DepthLimitExceeded(MAX_DEPTH)),
+            IdTree::from_str(&nested(MAX_DEPTH + 1))
+        );
+
+        let events = "(0,".repeat(MAX_DEPTH + 1) + "1" + &",0)".repeat(MAX_DEPTH + 1);
+        assert_eq!(
+            Err(ParseError::DepthLimitExceeded(3 * MAX_DEPTH)),
+            EventTree::<u32>::from_str(&events)
+        );
+
+        let text = nested(MAX_DEPTH + 1);
+        let mut p = Parser::new(text.chars()).with_max_depth(MAX_DEPTH + 1);
+        assert_eq!(Ok(MAX_DEPTH + 1), p.take_id_tree().map(|i| i.depth()));
+        let mut p = Parser::new(text.chars()).with_max_depth(3);
+        assert_eq!(Err(ParseError::DepthLimitExceeded(3)), p.take_id_tree());
+    }
+
+    #[test]
+    fn test_parser_epoch() {
+        let s: Stamp = Stamp::seed().event().rebase(&EventTree::leaf(1));
+        assert_eq!("(1,0)@1", s.to_string());
+        assert_eq!(Ok(s.clone()), Stamp::from_str("(1,0)@1"));
+        assert_eq!(Ok(s), Stamp::from_str(" (1, 0) @ 1 "));
+        assert_eq!(Stamp::<u32>::from_str("(1,0)"), Stamp::from_str("(1,0)@0"));
+        assert_eq!(
+            Err(ParseError::EndOfString(6)),
+            Stamp::<u32>::from_str("(1,0)@")
+        );
+    }
+
+    #[test]
+    fn test_parser_u64_counters() {
+        let s1 = "((1,0),(5000000000,1,0))";
+        let s: Stamp<u64> = Stamp::from_str(s1).expect("parse stamp");
+        assert_eq!(s1, s.to_string());
+        assert!(matches!(
+            Stamp::<u32>::from_str(s1),
+            Err(ParseError::ParseIntError(_, 8))
+        ));
     }
 
     #[test]
@@ -222,7 +621,7 @@ mod tests {
     #[test]
     fn test_parser_event_tree_string_round_trip() {
         let s1 = "(2,1,(0,0,1))";
-        let i = EventTree::from_str(s1).expect("parse eventtree");
+        let i: EventTree = EventTree::from_str(s1).expect("parse eventtree");
         let s2 = i.to_string();
         assert_eq!(s1, s2);
     }
@@ -230,8 +629,40 @@ mod tests {
     #[test]
     fn test_parser_stamp_string_round_trip() {
         let s1 = "((1,(0,1)),(2,1,(0,0,1)))";
-        let i = Stamp::from_str(s1).expect("parse stamp");
+        let i: Stamp = Stamp::from_str(s1).expect("parse stamp");
         let s2 = i.to_string();
         assert_eq!(s1, s2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_stamp_delta_round_trip() {
+        for s1 in [
+            "(=,=)",
+            "(=,(1,=,4))",
+            "((0,1),=)",
+            "(1,(0,(0,1,0),=))",
+            "(=,=)@0",
+            "(=,(0,=,1))@3",
+        ] {
+            let delta: StampDelta = StampDelta::from_str(s1).expect("parse delta");
+            assert_eq!(s1, delta.to_string());
+        }
+        assert_eq!(
+            Ok(EventDelta::Node {
+                n: 2,
+                left: Box::new(EventDelta::Same),
+                right: Box::new(EventDelta::Leaf { n: 0 }),
+            }),
+            EventDelta::<u32>::from_str(" ( 2 , = , 0 ) ")
+        );
+        assert_eq!(
+            Err(ParseError::UnexpectedChar('=', 1)),
+            Stamp::<u32>::from_str("(=,=)")
+        );
+
+        let base: Stamp = Stamp::from_str("((0,1),(1,(0,2,0),3))").unwrap();
+        let delta = base.event().delta_from(&base);
+        assert_eq!("(=,(1,=,4))", delta.to_string());
+        assert_eq!(Ok(delta), StampDelta::from_str("(=,(1,=,4))"));
+    }
+}
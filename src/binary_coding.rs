@@ -0,0 +1,834 @@
+//! The compact binary coding from section 6 of the itc2008 paper.
+//!
+//! Id trees use a 2 bit tag per node, event trees a variable length prefix
+//! code that elides zero leaves, and counters are written with an Elias style
+//! code that starts at 2 bits and grows one bit at a time:
+//!
+//! ```text
+//! enc(0)            = <0:2, 0:1>
+//! enc(1)            = <0:2, 1:1>
+//! enc((0, i))       = <1:2, enc(i)>
+//! enc((i, 0))       = <2:2, enc(i)>
+//! enc((il, ir))     = <3:2, enc(il), enc(ir)>
+//!
+//! enc((0, 0, er))   = <0:1, 0:2, enc(er)>
+//! enc((0, el, 0))   = <0:1, 1:2, enc(el)>
+//! enc((0, el, er))  = <0:1, 2:2, enc(el), enc(er)>
+//! enc((n, 0, er))   = <0:1, 3:2, 0:1, 0:1, enc(n), enc(er)>
+//! enc((n, el, 0))   = <0:1, 3:2, 0:1, 1:1, enc(n), enc(el)>
+//! enc((n, el, er))  = <0:1, 3:2, 1:1, enc(n), enc(el), enc(er)>
+//! enc(n)            = <1:1, encn(n, 2)>
+//!
+//! encn(n, B)        = <0:1, n:B>                        if n < 2^B
+//! encn(n, B)        = <1:1, encn(n - 2^B, B + 1)>      otherwise
+//! ```
+//!
+//! A stamp is its id followed by its event tree as in the paper, then its
+//! epoch as `<0:1>` for epoch 0 or `<1:1, encn(epoch, 2)>`, so each stamp
+//! ends where its reader can tell and stamps can be written back to back.
+//! A stamp delta is `<0:1>` for an unchanged id or `<1:1, enc(i)>`, followed
+//! by its event delta and the same epoch suffix, with `<0:1>` for an
+//! unchanged epoch. Each subtree of an event delta is either the base's or
+//! spelled out:
+//!
+//! ```text
+//! encd(=)           = <0:1>
+//! encd(n)           = <1:1, 1:1, encn(n, 2)>
+//! encd((n, dl, dr)) = <1:1, 0:1, encn(n, 2), encd(dl), encd(dr)>
+//! ```
+//!
+//! Bits are packed most significant first and the output is padded with zero
+//! bits to a whole byte. The decoder rejects trees nested deeper than
+//! `MAX_DEPTH`, unless the limit is changed with `BitReader::with_max_depth`.
+
+use std::error;
+use std::fmt;
+
+use crate::counter::Counter;
+use crate::delta::{EventDelta, StampDelta};
+use crate::{EventTree, IdTree, Stamp, MAX_DEPTH};
+
+/// Errors produced while decoding the binary coding.
+///
+/// Every variant carries the bit offset at which the problem was detected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a tree.
+    UnexpectedEnd(usize),
+    /// A counter does not fit in the event tree's counter type.
+    CounterOverflow(usize),
+    /// A bit pattern that no tree encodes, e.g. a node where a counter is expected.
+    InvalidCode(usize),
+    /// Non-zero padding or extra bytes follow the encoded value.
+    TrailingData(usize),
+    /// A tree node is nested deeper than `MAX_DEPTH`.
+    DepthLimitExceeded(usize),
+}
+
+impl DecodeError {
+    pub fn position(&self) -> usize {
+        match *self {
+            DecodeError::UnexpectedEnd(pos) => pos,
+            DecodeError::CounterOverflow(pos) => pos,
+            DecodeError::InvalidCode(pos) => pos,
+            DecodeError::TrailingData(pos) => pos,
+            DecodeError::DepthLimitExceeded(pos) => pos,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnexpectedEnd(pos) => write!(f, "unexpected end of input at bit {}", pos),
+            DecodeError::CounterOverflow(pos) => write!(f, "counter overflow at bit {}", pos),
+            DecodeError::InvalidCode(pos) => write!(f, "invalid code at bit {}", pos),
+            DecodeError::TrailingData(pos) => write!(f, "trailing data at bit {}", pos),
+            DecodeError::DepthLimitExceeded(pos) => {
+                write!(f, "tree exceeds the depth limit at bit {}", pos)
+            }
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Appends bits, most significant first, to a byte buffer.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    /// Number of bits written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Writes the low `width` bits of `value`.
+    pub fn write_bits(&mut self, value: u64, width: u32) {
+        for b in (0..width).rev() {
+            self.write_bit((value >> b) & 1 == 1);
+        }
+    }
+
+    /// Writes `n` with the paper's `encn(n, 2)` counter code.
+    pub fn write_number<N: Counter>(&mut self, n: N) {
+        // u128 so the prefix sums cannot overflow for u64 counters
+        let mut n = n.to_u64() as u128;
+        let mut width = 2;
+        while n >= 1 << width {
+            self.write_bit(true);
+            n -= 1 << width;
+            width += 1;
+        }
+        self.write_bit(false);
+        self.write_bits(n as u64, width);
+    }
+
+    /// Writes a node's counter as `enc(n) = <1:1, encn(n, 2)>`.
+    fn write_counter<N: Counter>(&mut self, n: N) {
+        self.write_bit(true);
+        self.write_number(n);
+    }
+
+    pub fn write_id_tree(&mut self, i: &IdTree) {
+        match *i {
+            IdTree::Leaf { i } => {
+                self.write_bits(0, 2);
+                self.write_bit(i);
+            }
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => {
+                if **left == IdTree::zero() {
+                    self.write_bits(1, 2);
+                    self.write_id_tree(right);
+                } else if **right == IdTree::zero() {
+                    self.write_bits(2, 2);
+                    self.write_id_tree(left);
+                } else {
+                    self.write_bits(3, 2);
+                    self.write_id_tree(left);
+                    self.write_id_tree(right);
+                }
+            }
+        }
+    }
+
+    pub fn write_event_tree<N: Counter>(&mut self, e: &EventTree<N>) {
+        match *e {
+            EventTree::Leaf { n } => self.write_counter(n),
+            EventTree::Node {
+                n,
+                ref left,
+                ref right,
+            } => {
+                self.write_bit(false);
+                let left_zero = **left == EventTree::new_zero();
+                let right_zero = **right == EventTree::new_zero();
+                if n == N::ZERO {
+                    if left_zero {
+                        self.write_bits(0, 2);
+                        self.write_event_tree(right);
+                    } else if right_zero {
+                        self.write_bits(1, 2);
+                        self.write_event_tree(left);
+                    } else {
+                        self.write_bits(2, 2);
+                        self.write_event_tree(left);
+                        self.write_event_tree(right);
+                    }
+                } else {
+                    self.write_bits(3, 2);
+                    if left_zero {
+                        self.write_bits(0, 2);
+                        self.write_counter(n);
+                        self.write_event_tree(right);
+                    } else if right_zero {
+                        self.write_bits(1, 2);
+                        self.write_counter(n);
+                        self.write_event_tree(left);
+                    } else {
+                        self.write_bit(true);
+                        self.write_counter(n);
+                        self.write_event_tree(left);
+                        self.write_event_tree(right);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn write_stamp<N: Counter>(&mut self, s: &Stamp<N>) {
+        self.write_id_tree(&s.i);
+        self.write_event_tree(&s.e);
+        self.write_epoch(Some(s.epoch).filter(|&epoch| epoch != 0));
+    }
+
+    fn write_epoch(&mut self, epoch: Option<u32>) {
+        match epoch {
+            Some(epoch) => {
+                self.write_bit(true);
+                self.write_number(epoch);
+            }
+            None => self.write_bit(false),
+        }
+    }
+
+    pub fn write_event_delta<N: Counter>(&mut self, d: &EventDelta<N>) {
+        match *d {
+            EventDelta::Same => self.write_bit(false),
+            EventDelta::Leaf { n } => {
+                self.write_bits(3, 2);
+                self.write_number(n);
+            }
+            EventDelta::Node {
+                n,
+                ref left,
+                ref right,
+            } => {
+                self.write_bits(2, 2);
+                self.write_number(n);
+                self.write_event_delta(left);
+                self.write_event_delta(right);
+            }
+        }
+    }
+
+    pub fn write_stamp_delta<N: Counter>(&mut self, d: &StampDelta<N>) {
+        match d.id() {
+            Some(i) => {
+                self.write_bit(true);
+                self.write_id_tree(i);
+            }
+            None => self.write_bit(false),
+        }
+        self.write_event_delta(d.events());
+        self.write_epoch(d.epoch());
+    }
+
+    /// Returns the buffer, padded with zero bits to a whole byte.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads bits, most significant first, from a byte buffer.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            bytes,
+            pos: 0,
+            depth: 0,
+            max_depth: MAX_DEPTH,
+        }
+    }
+
+    /// Rejects trees nested deeper than `max_depth` instead of `MAX_DEPTH`.
+    pub fn with_max_depth(self, max_depth: usize) -> BitReader<'a> {
+        BitReader { max_depth, ..self }
+    }
+
+    /// Bit offset of the next bit to be read.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, DecodeError> {
+        let byte = self
+            .bytes
+            .get(self.pos / 8)
+            .ok_or(DecodeError::UnexpectedEnd(self.pos))?;
+        let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    pub fn read_bits(&mut self, width: u32) -> Result<u64, DecodeError> {
+        let mut value = 0;
+        for _ in 0..width {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    pub fn read_number<N: Counter>(&mut self) -> Result<N, DecodeError> {
+        let start = self.pos;
+        let mut base: u128 = 0;
+        let mut width = 2;
+        while self.read_bit()? {
+            base += 1 << width;
+            width += 1;
+            if width > N::BITS {
+                return Err(DecodeError::CounterOverflow(start));
+            }
+        }
+        let n = base + self.read_bits(width)? as u128;
+        u64::try_from(n)
+            .ok()
+            .and_then(N::from_u64)
+            .ok_or(DecodeError::CounterOverflow(start))
+    }
+
+    fn read_counter<N: Counter>(&mut self) -> Result<N, DecodeError> {
+        let pos = self.pos;
+        if !self.read_bit()? {
+            return Err(DecodeError::InvalidCode(pos));
+        }
+        self.read_number()
+    }
+
+    pub fn read_id_tree(&mut self) -> Result<IdTree, DecodeError> {
+        let pos = self.pos;
+        let tag = self.read_bits(2)?;
+        if tag == 0 {
+            return Ok(IdTree::leaf(self.read_bit()?));
+        }
+
+        self.enter_node(pos)?;
+        let tree = match tag {
+            1 => {
+                let right = self.read_id_tree()?;
+                IdTree::node(Box::new(IdTree::zero()), Box::new(right))
+            }
+            2 => {
+                let left = self.read_id_tree()?;
+                IdTree::node(Box::new(left), Box::new(IdTree::zero()))
+            }
+            _ => {
+                let left = self.read_id_tree()?;
+                let right = self.read_id_tree()?;
+                IdTree::node(Box::new(left), Box::new(right))
+            }
+        };
+        self.depth -= 1;
+        Ok(tree)
+    }
+
+    pub fn read_event_tree<N: Counter>(&mut self) -> Result<EventTree<N>, DecodeError> {
+        let pos = self.pos;
+        if self.read_bit()? {
+            return Ok(EventTree::new_leaf(self.read_number()?));
+        }
+
+        self.enter_node(pos)?;
+        let (n, left, right) = match self.read_bits(2)? {
+            0 => (N::ZERO, EventTree::new_zero(), self.read_event_tree()?),
+            1 => (N::ZERO, self.read_event_tree()?, EventTree::new_zero()),
+            2 => {
+                let left = self.read_event_tree()?;
+                (N::ZERO, left, self.read_event_tree()?)
+            }
+            _ => {
+                if self.read_bit()? {
+                    let n = self.read_counter()?;
+                    let left = self.read_event_tree()?;
+                    (n, left, self.read_event_tree()?)
+                } else if self.read_bit()? {
+                    let n = self.read_counter()?;
+                    (n, self.read_event_tree()?, EventTree::new_zero())
+                } else {
+                    let n = self.read_counter()?;
+                    (n, EventTree::new_zero(), self.read_event_tree()?)
+                }
+            }
+        };
+        self.depth -= 1;
+
+        Ok(EventTree::new_node(n, Box::new(left), Box::new(right)))
+    }
+
+    // descends into a tree node that starts at bit pos
+    fn enter_node(&mut self, pos: usize) -> Result<(), DecodeError> {
+        if self.depth == self.max_depth {
+            return Err(DecodeError::DepthLimitExceeded(pos));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn read_stamp<N: Counter>(&mut self) -> Result<Stamp<N>, DecodeError> {
+        let i = self.read_id_tree()?;
+        let e = self.read_event_tree()?;
+        let epoch = self.read_epoch()?.unwrap_or(0);
+        Ok(Stamp { i, e, epoch })
+    }
+
+    fn read_epoch(&mut self) -> Result<Option<u32>, DecodeError> {
+        if self.read_bit()? {
+            Ok(Some(self.read_number()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn read_event_delta<N: Counter>(&mut self) -> Result<EventDelta<N>, DecodeError> {
+        let pos = self.pos;
+        if !self.read_bit()? {
+            return Ok(EventDelta::Same);
+        }
+        if self.read_bit()? {
+            return Ok(EventDelta::Leaf {
+                n: self.read_number()?,
+            });
+        }
+
+        self.enter_node(pos)?;
+        let n = self.read_number()?;
+        let left = self.read_event_delta()?;
+        let right = self.read_event_delta()?;
+        self.depth -= 1;
+        Ok(EventDelta::Node {
+            n,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    pub fn read_stamp_delta<N: Counter>(&mut self) -> Result<StampDelta<N>, DecodeError> {
+        let i = if self.read_bit()? {
+            Some(self.read_id_tree()?)
+        } else {
+            None
+        };
+        let e = self.read_event_delta()?;
+        let delta = StampDelta::new(i, e);
+        Ok(match self.read_epoch()? {
+            Some(epoch) => delta.with_epoch(epoch),
+            None => delta,
+        })
+    }
+
+    /// Succeeds only if the remaining bits are the zero padding of the last byte.
+    pub fn finish(&mut self) -> Result<(), DecodeError> {
+        let pos = self.pos;
+        let remaining = self.bytes.len() * 8 - pos;
+        if remaining >= 8 || self.read_bits(remaining as u32)? != 0 {
+            return Err(DecodeError::TrailingData(pos));
+        }
+        Ok(())
+    }
+}
+
+impl IdTree {
+    pub fn encode_bits(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_id_tree(self);
+        w.into_bytes()
+    }
+
+    pub fn decode_bits(bytes: &[u8]) -> Result<IdTree, DecodeError> {
+        let mut r = BitReader::new(bytes);
+        let i = r.read_id_tree()?;
+        r.finish()?;
+        Ok(i)
+    }
+}
+
+impl<N: Counter> EventTree<N> {
+    pub fn encode_bits(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_event_tree(self);
+        w.into_bytes()
+    }
+
+    pub fn decode_bits(bytes: &[u8]) -> Result<EventTree<N>, DecodeError> {
+        let mut r = BitReader::new(bytes);
+        let e = r.read_event_tree()?;
+        r.finish()?;
+        Ok(e)
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    pub fn encode_bits(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_stamp(self);
+        w.into_bytes()
+    }
+
+    pub fn decode_bits(bytes: &[u8]) -> Result<Stamp<N>, DecodeError> {
+        let mut r = BitReader::new(bytes);
+        let s = r.read_stamp()?;
+        r.finish()?;
+        Ok(s)
+    }
+}
+
+impl<N: Counter> StampDelta<N> {
+    pub fn encode_bits(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write_stamp_delta(self);
+        w.into_bytes()
+    }
+
+    pub fn decode_bits(bytes: &[u8]) -> Result<StampDelta<N>, DecodeError> {
+        let mut r = BitReader::new(bytes);
+        let d = r.read_stamp_delta()?;
+        r.finish()?;
+        Ok(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntervalTreeClock;
+    use std::str::FromStr;
+
+    fn round_trip(s: &str) {
+        let stamp: Stamp = Stamp::from_str(s).expect("parse stamp");
+        let bytes = stamp.encode_bits();
+        assert_eq!(Ok(stamp), Stamp::decode_bits(&bytes), "{}", s);
+    }
+
+    #[test]
+    fn test_number_coding() {
+        for n in [0, 1, 3, 4, 11, 12, 27, 28, 1000, u32::MAX - 1, u32::MAX] {
+            let mut w = BitWriter::new();
+            w.write_number(n);
+            let bytes = w.into_bytes();
+            let mut r = BitReader::new(&bytes);
+            assert_eq!(Ok(n), r.read_number());
+        }
+    }
+
+    #[test]
+    fn test_number_widths() {
+        let widths = [(0u32, 3), (3, 3), (4, 5), (11, 5), (12, 7), (28, 9)];
+        for (n, len) in widths {
+            let mut w = BitWriter::new();
+            w.write_number(n);
+            assert_eq!(len, w.len(), "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_seed_encoding() {
+        // <0:2, 1:1> <1:1, 0:1, 0:2> padded to a byte
+        assert_eq!(vec![0b0011_0000], Stamp::seed().encode_bits());
+    }
+
+    #[test]
+    fn test_id_tree_round_trip() {
+        for s in ["0", "1", "(0,1)", "(1,0)", "((1,0),(0,1))", "(1,(0,(1,0)))"] {
+            let i = IdTree::from_str(s).expect("parse idtree");
+            assert_eq!(Ok(i.clone()), IdTree::decode_bits(&i.encode_bits()));
+        }
+    }
+
+    #[test]
+    fn test_event_tree_round_trip() {
+        for s in [
+            "0",
+            "17",
+            "(0,0,1)",
+            "(0,1,0)",
+            "(0,1,2)",
+            "(5,0,1)",
+            "(5,1,0)",
+            "(5,1,(0,0,2))",
+            "(0,0,0)",
+        ] {
+            let e: EventTree = EventTree::from_str(s).expect("parse eventtree");
+            assert_eq!(Ok(e.clone()), EventTree::decode_bits(&e.encode_bits()));
+        }
+    }
+
+    #[test]
+    fn test_stamp_round_trip() {
+        round_trip("(1,0)");
+        round_trip("((1,0),(0,1,0))");
+        round_trip("(((0,1),0),(1,(0,1,0),4294967295))");
+
+        let (a, b) = Stamp::seed().fork();
+        let c = a.event().join(&b.event().event());
+        assert_eq!(Ok(c.clone()), Stamp::decode_bits(&c.encode_bits()));
+    }
+
+    #[test]
+    fn test_epoch() {
+        let s = Stamp::seed().event().rebase(&EventTree::leaf(1));
+        // the seed's 7 bits, then <1:1, 0:1, 1:2>
+        assert_eq!(vec![0b0011_0001, 0b0010_0000], s.encode_bits());
+        assert_eq!(Ok(s.clone()), Stamp::decode_bits(&s.encode_bits()));
+
+        let s = s.rebase(&EventTree::zero()).rebase(&EventTree::zero());
+        assert_eq!(3, s.epoch());
+        assert_eq!(Ok(s.clone()), Stamp::decode_bits(&s.encode_bits()));
+    }
+
+    #[test]
+    fn test_back_to_back() {
+        let stamps: Vec<Stamp> = vec![
+            Stamp::seed(),
+            Stamp::from_str("((1,0),(0,1,0))").unwrap(),
+            Stamp::seed().event().rebase(&EventTree::leaf(1)),
+            Stamp::from_str("(0,(2,0,1))").unwrap(),
+        ];
+        let mut w = BitWriter::new();
+        for s in &stamps {
+            w.write_stamp(s);
+        }
+        let bytes = w.into_bytes();
+        let mut r = BitReader::new(&bytes);
+        for s in &stamps {
+            assert_eq!(Ok(s.clone()), r.read_stamp());
+        }
+        assert_eq!(Ok(()), r.finish());
+
+        let base = &stamps[1];
+        let deltas = [
+            stamps[3].delta_from(base),
+            base.event().delta_from(base),
+            stamps[2].delta_from(base),
+            base.delta_from(base),
+        ];
+        let mut w = BitWriter::new();
+        for d in &deltas {
+            w.write_stamp_delta(d);
+        }
+        let bytes = w.into_bytes();
+        let mut r = BitReader::new(&bytes);
+        for d in &deltas {
+            assert_eq!(Ok(d.clone()), r.read_stamp_delta());
+        }
+        assert_eq!(Ok(()), r.finish());
+    }
+
+    #[test]
+    fn test_u64_counters() {
+        for n in [0, u32::MAX as u64 + 1, u64::MAX - 1, u64::MAX] {
+            let mut w = BitWriter::new();
+            w.write_number(n);
+            let bytes = w.into_bytes();
+            let mut r = BitReader::new(&bytes);
+            assert_eq!(Ok(n), r.read_number());
+        }
+
+        let s: Stamp<u64> = Stamp::from_str("((1,0),(5000000000,1,0))").unwrap();
+        assert_eq!(Ok(s.clone()), Stamp::decode_bits(&s.encode_bits()));
+        assert_eq!(
+            Err(DecodeError::CounterOverflow(11)),
+            Stamp::<u32>::decode_bits(&s.encode_bits())
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        assert_eq!(
+            Err(DecodeError::UnexpectedEnd(0)),
+            Stamp::<u32>::decode_bits(&[])
+        );
+
+        let bytes = Stamp::<u32>::from_str("(((0,1),0),(1,(0,1,0),1000))")
+            .unwrap()
+            .encode_bits();
+        for len in 0..bytes.len() {
+            match Stamp::<u32>::decode_bits(&bytes[..len]) {
+                Err(DecodeError::UnexpectedEnd(_)) => {}
+                other => panic!("decoded truncated input: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_trailing_data() {
+        // the seed's 7 bits and its epoch bit fill the first byte
+        assert_eq!(
+            Err(DecodeError::TrailingData(8)),
+            Stamp::<u32>::decode_bits(&[0b0011_0000, 0])
+        );
+        // 5 bits of id, 4 of events and the epoch bit, then the padding
+        let mut bytes = Stamp::<u32>::from_str("((1,0),0)").unwrap().encode_bits();
+        assert_eq!(2, bytes.len());
+        bytes[1] |= 1;
+        assert_eq!(
+            Err(DecodeError::TrailingData(10)),
+            Stamp::<u32>::decode_bits(&bytes)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_code() {
+        // a node with a non-zero counter whose counter is itself encoded as a node
+        let mut w = BitWriter::new();
+        w.write_bits(0b0111, 4);
+        w.write_bit(false);
+        assert_eq!(
+            Err(DecodeError::InvalidCode(4)),
+            EventTree::<u32>::decode_bits(&w.into_bytes())
+        );
+    }
+
+    #[test]
+    fn test_decode_counter_overflow() {
+        // an event leaf whose counter prefix never terminates within 32 bits
+        let mut w = BitWriter::new();
+        w.write_bit(true);
+        for _ in 0..40 {
+            w.write_bit(true);
+        }
+        assert_eq!(
+            Err(DecodeError::CounterOverflow(1)),
+            EventTree::<u32>::decode_bits(&w.into_bytes())
+        );
+    }
+
+    #[test]
+    fn test_decode_depth_limit() {
+        let mut i = IdTree::one();
+        for _ in 0..MAX_DEPTH {
+            i = IdTree::node(Box::new(IdTree::zero()), Box::new(i));
+        }
+        assert_eq!(Ok(i.clone()), IdTree::decode_bits(&i.encode_bits()));
+
+        // each (0, i) node is a 2 bit tag
+        let deeper = IdTree::node(Box::new(IdTree::zero()), Box::new(i));
+        assert_eq!(
+            Err(DecodeError::DepthLimitExceeded(2 * MAX_DEPTH)),
+            IdTree::decode_bits(&deeper.encode_bits())
+        );
+        let bytes = deeper.encode_bits();
+        let mut r = BitReader::new(&bytes).with_max_depth(MAX_DEPTH + 1);
+        assert_eq!(Ok(deeper), r.read_id_tree());
+        let mut r = BitReader::new(&bytes).with_max_depth(3);
+        assert_eq!(Err(DecodeError::DepthLimitExceeded(6)), r.read_id_tree());
+
+        let mut e = EventTree::leaf(1);
+        for _ in 0..=MAX_DEPTH {
+            e = EventTree::node(0, Box::new(EventTree::zero()), Box::new(e));
+        }
+        // each (0, 0, e) node is a 3 bit prefix
+        assert_eq!(
+            Err(DecodeError::DepthLimitExceeded(3 * MAX_DEPTH)),
+            EventTree::<u32>::decode_bits(&e.encode_bits())
+        );
+    }
+
+    #[test]
+    fn test_stamp_delta_round_trip() {
+        for s in [
+            "(=,=)",
+            "(=,(1,=,4))",
+            "((0,1),=)",
+            "(1,(0,(0,1,0),=))",
+            "(=,=)@0",
+            "(=,(0,=,1))@3",
+        ] {
+            let d: StampDelta = StampDelta::from_str(s).expect("parse delta");
+            assert_eq!(
+                Ok(d.clone()),
+                StampDelta::decode_bits(&d.encode_bits()),
+                "{}",
+                s
+            );
+        }
+
+        // <0:1> <0:1> padded to a byte
+        assert_eq!(
+            vec![0],
+            StampDelta::<u32>::from_str("(=,=)").unwrap().encode_bits()
+        );
+
+        let mut replicas = Stamp::seed().fork_n(16);
+        for k in 0..64 {
+            let (msg, _) = replicas[(k + 5) % 16].peek();
+            replicas[k % 16] = replicas[k % 16].event().join(&msg);
+        }
+        let s = &replicas[3];
+        let delta = s.event().delta_from(s);
+        assert!(delta.encode_bits().len() < s.encode_bits().len());
+        assert_eq!(
+            Ok(delta.clone()),
+            StampDelta::decode_bits(&delta.encode_bits())
+        );
+    }
+
+    #[test]
+    fn test_decode_delta_depth_limit() {
+        let mut d: EventDelta = EventDelta::Leaf { n: 1 };
+        for _ in 0..=MAX_DEPTH {
+            d = EventDelta::Node {
+                n: 0,
+                left: Box::new(EventDelta::Same),
+                right: Box::new(d),
+            };
+        }
+        // after the id bit, each (0, =, d) node is 6 bits
+        assert_eq!(
+            Err(DecodeError::DepthLimitExceeded(1 + 6 * MAX_DEPTH)),
+            StampDelta::<u32>::decode_bits(&StampDelta::new(None, d).encode_bits())
+        );
+    }
+}
@@ -1,38 +1,45 @@
+//! How much `grow` inflates an event tree, and the strategies `event`
+//! uses to pick which owned subtree to grow.
 
 use std::cmp;
+use std::fmt;
 use std::ops::Add;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::dyadic::Dyadic;
+use crate::error::Error;
+
+/// The cost of a grow from the paper: `Small(n)` for `n` nodes walked to a
+/// leaf the replica owns, `Big(n, c)` once leaves had to be split into new
+/// nodes. Any `Big` cost is greater than any `Small` one.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Cost {
     Small(u32),
-    Big(u32, Box<Cost>)
+    Big(u32, Box<Cost>),
+}
+
+impl Ord for Cost {
+    fn cmp(&self, rhs: &Cost) -> cmp::Ordering {
+        match (self, rhs) {
+            (&Cost::Small(l), &Cost::Small(r)) => l.cmp(&r),
+            (&Cost::Small(_), &Cost::Big(..)) => cmp::Ordering::Less,
+            (&Cost::Big(..), &Cost::Small(_)) => cmp::Ordering::Greater,
+            (&Cost::Big(l, ref cl), &Cost::Big(r, ref cr)) => cl.cmp(cr).then(l.cmp(&r)),
+        }
+    }
 }
 
 impl PartialOrd for Cost {
     fn partial_cmp(&self, rhs: &Cost) -> Option<cmp::Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+/// Writes `Small(n)` as `n` and `Big(n, c)` as `(n,c)`.
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Cost::Small(l) => {
-                if let Cost::Small(r) = *rhs {
-                    PartialOrd::partial_cmp(&l, &r)
-                } else {
-                    Some(cmp::Ordering::Less)
-                }
-            },
-            Cost::Big(l, ref cl) => {
-                if let Cost::Big(r, ref cr) = *rhs {
-                    if let Some(eq) = PartialOrd::partial_cmp(cl.as_ref(), cr.as_ref()) {
-                        if eq == cmp::Ordering::Equal {
-                            PartialOrd::partial_cmp(&l, &r)
-                        } else {
-                            Some(eq)
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(cmp::Ordering::Greater)
-                }
-            }
+            Cost::Small(n) => write!(f, "{}", n),
+            Cost::Big(n, ref c) => write!(f, "({},{})", n, c),
         }
     }
 }
@@ -42,12 +49,8 @@ impl Add<u32> for Cost {
 
     fn add(self, n: u32) -> Cost {
         match self {
-            Cost::Small(m) => {
-                Cost::Small(m + n)
-            },
-            Cost::Big(m, c) => {
-                Cost::Big(m + n, c)
-            }
+            Cost::Small(m) => Cost::Small(m + n),
+            Cost::Big(m, c) => Cost::Big(m + n, c),
         }
     }
 }
@@ -60,14 +63,178 @@ impl Cost {
     pub fn shift(self) -> Cost {
         match self {
             Cost::Small(_n) => Cost::Big(0, Box::new(self)),
-            Cost::Big(n, c) => Cost::Big(0, Box::new(c.shift() + n))
+            Cost::Big(n, c) => Cost::Big(0, Box::new(c.shift() + n)),
+        }
+    }
+}
+
+/// Picks which subtree `Stamp::event` grows when the replica owns part of
+/// both, and its counters cannot simply be filled.
+///
+/// Before anything is grown, every side of every node is summarised in one
+/// bottom-up pass as a `GrowSide`, and the strategy picks a side at each
+/// node where the replica owns part of both. The chosen sides are then
+/// grown down to a leaf the replica owns.
+pub trait GrowStrategy {
+    fn grow_left(&self, left: &GrowSide, right: &GrowSide) -> bool;
+}
+
+/// One side of a node a `GrowStrategy` chooses between: an event subtree
+/// and the part of the id over it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrowSide {
+    cost: Cost,
+    depth: usize,
+    grown_depth: usize,
+    owned: Option<Dyadic>,
+}
+
+impl GrowSide {
+    /// The `Cost` of the cheapest grow of this side.
+    pub fn cost(&self) -> &Cost {
+        &self.cost
+    }
+
+    /// The depth of the event subtree.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The least depth the event subtree can have once grown.
+    pub fn grown_depth(&self) -> usize {
+        self.grown_depth
+    }
+
+    /// The fraction of the side's interval the replica owns, or `None` if
+    /// the id below is too deep for a `Dyadic`.
+    pub fn owned(&self) -> Option<Dyadic> {
+        self.owned
+    }
+
+    pub(crate) fn into_cost(self) -> Cost {
+        self.cost
+    }
+
+    // a leaf the replica owns, which grows by incrementing it
+    pub(crate) fn owned_leaf() -> GrowSide {
+        GrowSide {
+            cost: Cost::zero(),
+            depth: 0,
+            grown_depth: 0,
+            owned: Some(Dyadic::one()),
+        }
+    }
+
+    // an event subtree of the given depth that the replica owns none of
+    pub(crate) fn unowned(depth: usize) -> GrowSide {
+        GrowSide {
+            cost: Cost::zero(),
+            depth,
+            grown_depth: depth,
+            owned: Some(Dyadic::zero()),
+        }
+    }
+
+    // summarises a node from its sides and picks the side to grow. a split
+    // node is an event leaf that growing first turns into a node of zeros.
+    // fails if neither side is owned, as under an id node such as (0,0).
+    pub(crate) fn node(
+        strategy: &dyn GrowStrategy,
+        left: GrowSide,
+        right: GrowSide,
+        split: bool,
+    ) -> Result<(GrowSide, bool), Error> {
+        let grow_left = match (left.is_owned(), right.is_owned()) {
+            (true, true) => strategy.grow_left(&left, &right),
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => return Err(Error::NonNormalIdTree),
+        };
+
+        // ties go right, as in the paper
+        let cheaper_left = !right.is_owned() || left.is_owned() && left.cost < right.cost;
+        let grown_left = cmp::max(left.grown_depth, right.depth);
+        let grown_right = cmp::max(left.depth, right.grown_depth);
+        let grown_depth = 1 + match (left.is_owned(), right.is_owned()) {
+            (true, true) => cmp::min(grown_left, grown_right),
+            (true, false) => grown_left,
+            _ => grown_right,
+        };
+        let depth = if split {
+            0
+        } else {
+            1 + cmp::max(left.depth, right.depth)
+        };
+        let owned = match (left.owned, right.owned) {
+            (Some(l), Some(r)) => l.checked_add(r).and_then(Dyadic::checked_half),
+            _ => None,
+        };
+
+        let cost = if cheaper_left { left.cost } else { right.cost } + 1;
+        let cost = if split { cost.shift() } else { cost };
+        let side = GrowSide {
+            cost,
+            depth,
+            grown_depth,
+            owned,
+        };
+        Ok((side, grow_left))
+    }
+
+    fn is_owned(&self) -> bool {
+        self.owned != Some(Dyadic::zero())
+    }
+}
+
+/// The strategy from the paper, and the default: grows the side with the
+/// smaller `Cost`, adding as few nodes as possible. Ties grow the right.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MinimalGrowth;
+
+impl GrowStrategy for MinimalGrowth {
+    fn grow_left(&self, left: &GrowSide, right: &GrowSide) -> bool {
+        left.cost < right.cost
+    }
+}
+
+/// Grows the side that leaves the event tree shallowest, falling back to
+/// `MinimalGrowth` if both are as deep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShallowGrowth;
+
+impl GrowStrategy for ShallowGrowth {
+    fn grow_left(&self, left: &GrowSide, right: &GrowSide) -> bool {
+        let depth_left = cmp::max(left.grown_depth, right.depth);
+        let depth_right = cmp::max(left.depth, right.grown_depth);
+        match depth_left.cmp(&depth_right) {
+            cmp::Ordering::Equal => MinimalGrowth.grow_left(left, right),
+            ordering => ordering == cmp::Ordering::Less,
+        }
+    }
+}
+
+/// Grows the side where the replica owns the larger part of the interval,
+/// falling back to `MinimalGrowth` if both parts are the same size or too
+/// finely split to measure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LargestRegion;
+
+impl GrowStrategy for LargestRegion {
+    fn grow_left(&self, left: &GrowSide, right: &GrowSide) -> bool {
+        match (left.owned, right.owned) {
+            (Some(l), Some(r)) if l != r => l > r,
+            _ => MinimalGrowth.grow_left(left, right),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Cost;
+    use super::*;
+    use crate::flat::FlatStamp;
+    use crate::overflow::OverflowPolicy;
+    use crate::{EventTree, IdTree, IntervalTreeClock, LessThanOrEqual, Stamp};
+    use std::str::FromStr;
 
     #[test]
     fn test_cmp_small() {
@@ -95,4 +262,154 @@ mod tests {
         assert!(a < b);
         assert!(b > a);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cmp_total() {
+        let mut costs = vec![
+            (Cost::zero() + 1).shift() + 2,
+            Cost::zero().shift().shift(),
+            Cost::zero() + 3,
+            Cost::zero().shift() + 1,
+            Cost::zero(),
+        ];
+        costs.sort();
+        assert_eq!(
+            vec![
+                Cost::zero(),
+                Cost::zero() + 3,
+                Cost::zero().shift() + 1,
+                (Cost::zero() + 1).shift() + 2,
+                Cost::zero().shift().shift()
+            ],
+            costs
+        );
+        assert_eq!(
+            Some(&(Cost::zero().shift() + 1)),
+            costs.iter().find(|c| **c > Cost::zero() + 3)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("3", format!("{}", Cost::zero() + 3));
+        assert_eq!(
+            "(0,(2,1))",
+            format!("{}", ((Cost::zero() + 1).shift() + 2).shift())
+        );
+    }
+
+    #[test]
+    fn test_strategies() {
+        // growing the left splits a leaf twice, the right splits one leaf two levels down
+        let s: Stamp = Stamp::from_str("((((1,0),0),(0,(0,(1,0)))),(0,0,(0,0,(0,0,1))))").unwrap();
+        assert_eq!(
+            s.event(),
+            s.event_with_strategy(&MinimalGrowth, OverflowPolicy::Error)
+                .unwrap()
+        );
+        assert_eq!(
+            "((((1,0),0),(0,(0,(1,0)))),(0,0,(0,0,(0,0,(1,1,0)))))",
+            format!("{}", s.event())
+        );
+        assert_eq!(
+            "((((1,0),0),(0,(0,(1,0)))),(0,(0,(0,1,0),0),(0,0,(0,0,1))))",
+            format!(
+                "{}",
+                s.event_with_strategy(&ShallowGrowth, OverflowPolicy::Error)
+                    .unwrap()
+            )
+        );
+
+        // half owned on the left, but only a quarter on the right needs no split
+        let s: Stamp = Stamp::from_str("(((1,0),(0,(0,1))),(0,0,(0,0,(0,0,1))))").unwrap();
+        assert_eq!(
+            "(((1,0),(0,(0,1))),(0,0,(0,0,(0,0,2))))",
+            format!("{}", s.event())
+        );
+        assert_eq!(
+            "(((1,0),(0,(0,1))),(0,(0,1,0),(0,0,(0,0,1))))",
+            format!(
+                "{}",
+                s.event_with_strategy(&LargestRegion, OverflowPolicy::Error)
+                    .unwrap()
+            )
+        );
+    }
+
+    // grows the leftmost owned counter, whatever it costs
+    struct LeftFirst;
+
+    impl GrowStrategy for LeftFirst {
+        fn grow_left(&self, _left: &GrowSide, _right: &GrowSide) -> bool {
+            true
+        }
+    }
+
+    // every strategy must still record an event the stamp did not see, the
+    // same way through each event verb and through `FlatStamp`
+    fn check_order(strategy: &dyn GrowStrategy) {
+        let mut replicas = Stamp::seed().fork_n(5);
+        for round in 0..40 {
+            let k = round % replicas.len();
+            let before = replicas[k].clone();
+            replicas[k] = replicas[k]
+                .event_with_strategy(strategy, OverflowPolicy::Error)
+                .unwrap();
+            assert!(before.leq(&replicas[k]) && !replicas[k].leq(&before));
+
+            let mut in_place = before.clone();
+            in_place
+                .event_mut_with_strategy(strategy, OverflowPolicy::Error)
+                .unwrap();
+            assert_eq!(replicas[k], in_place);
+            let flat = FlatStamp::from(&before)
+                .event_with_strategy(strategy, OverflowPolicy::Error)
+                .unwrap();
+            assert_eq!(replicas[k], Stamp::from(&flat));
+
+            let (msg, _) = replicas[(k + 2) % replicas.len()].peek();
+            replicas[k] = replicas[k].join(&msg);
+        }
+    }
+
+    #[test]
+    fn test_strategies_order_events() {
+        check_order(&MinimalGrowth);
+        check_order(&ShallowGrowth);
+        check_order(&LargestRegion);
+        check_order(&LeftFirst);
+    }
+
+    #[test]
+    fn test_custom_strategy() {
+        let s: Stamp = Stamp::from_str("(((1,0),(0,1)),0)").unwrap();
+        let strategy: Box<dyn GrowStrategy> = Box::new(LeftFirst);
+        assert_eq!(
+            "(((1,0),(0,1)),(0,(0,1,0),0))",
+            format!(
+                "{}",
+                s.event_with_strategy(&*strategy, OverflowPolicy::Error)
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_largest_region_deep_id() {
+        // the left part owned is too small for a Dyadic
+        let mut deep = IdTree::one();
+        for _ in 0..200 {
+            deep = IdTree::node(Box::new(deep), Box::new(IdTree::zero()));
+        }
+        let s: Stamp = Stamp::new(
+            IdTree::node(Box::new(deep), Box::new(IdTree::one())),
+            EventTree::zero(),
+        );
+        assert!(s.validate_within(usize::MAX).is_ok());
+        assert_eq!(
+            s.event(),
+            s.event_with_strategy(&LargestRegion, OverflowPolicy::Error)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Unsigned integer types that can be used as event counters.
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+/// An unsigned integer used to count events in an `EventTree`.
+///
+/// Implemented for `u8`, `u16`, `u32` and `u64`. `u32` is the default
+/// counter of `EventTree` and `Stamp`.
+pub trait Counter:
+    Copy
+    + Ord
+    + Default
+    + fmt::Debug
+    + fmt::Display
+    + FromStr<Err = ParseIntError>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Send
+    + Sync
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+    /// Width of the counter in bits.
+    const BITS: u32;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn to_u64(self) -> u64;
+    fn from_u64(n: u64) -> Option<Self>;
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {
+        $(
+            impl Counter for $t {
+                const ZERO: $t = 0;
+                const ONE: $t = 1;
+                const MAX: $t = <$t>::MAX;
+                const BITS: u32 = <$t>::BITS;
+
+                fn checked_add(self, rhs: $t) -> Option<$t> {
+                    <$t>::checked_add(self, rhs)
+                }
+
+                fn checked_sub(self, rhs: $t) -> Option<$t> {
+                    <$t>::checked_sub(self, rhs)
+                }
+
+                fn saturating_add(self, rhs: $t) -> $t {
+                    <$t>::saturating_add(self, rhs)
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(n: u64) -> Option<$t> {
+                    <$t>::try_from(n).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(u8, u16, u32, u64);
@@ -0,0 +1,289 @@
+//! Stamps described relative to a base stamp the receiver already holds.
+//!
+//! Between two stamps of the same replica most of the event tree is
+//! unchanged, so a delta only spells out the subtrees that differ from the
+//! base at the same position and marks the rest as the same. The id and
+//! epoch are only included if they changed. Both codings support deltas.
+
+use crate::counter::Counter;
+use crate::error::Error;
+use crate::{EventTree, IdTree, Stamp, MAX_DEPTH};
+
+/// An event tree described against the subtree at the same position in a base.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventDelta<N = u32> {
+    /// The same subtree as the base.
+    Same,
+    Leaf {
+        n: N,
+    },
+    Node {
+        n: N,
+        left: Box<EventDelta<N>>,
+        right: Box<EventDelta<N>>,
+    },
+}
+
+/// A stamp described against a base; see `Stamp::delta_from`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StampDelta<N = u32> {
+    i: Option<IdTree>,
+    e: EventDelta<N>,
+    epoch: Option<u32>,
+}
+
+impl<N: Counter> StampDelta<N> {
+    /// `i` is `None` if the id is the base's. The epoch is the base's
+    /// unless set with `with_epoch`.
+    pub fn new(i: Option<IdTree>, e: EventDelta<N>) -> StampDelta<N> {
+        StampDelta { i, e, epoch: None }
+    }
+
+    pub fn with_epoch(self, epoch: u32) -> StampDelta<N> {
+        StampDelta {
+            epoch: Some(epoch),
+            ..self
+        }
+    }
+
+    /// The new id, or `None` if it is the base's.
+    pub fn id(&self) -> Option<&IdTree> {
+        self.i.as_ref()
+    }
+
+    pub fn events(&self) -> &EventDelta<N> {
+        &self.e
+    }
+
+    /// The new epoch, or `None` if it is the base's.
+    pub fn epoch(&self) -> Option<u32> {
+        self.epoch
+    }
+
+    /// Whether the stamp is the base itself.
+    pub fn is_empty(&self) -> bool {
+        self.i.is_none() && self.e == EventDelta::Same && self.epoch.is_none()
+    }
+}
+
+impl<N: Counter> EventDelta<N> {
+    /// The delta turning `base` into `target`, or spelling out all of
+    /// `target` if there is no base subtree.
+    pub fn new_delta(target: &EventTree<N>, base: Option<&EventTree<N>>) -> EventDelta<N> {
+        match (target, base) {
+            (&EventTree::Leaf { n }, Some(&EventTree::Leaf { n: m })) if n == m => EventDelta::Same,
+            (&EventTree::Leaf { n }, _) => EventDelta::Leaf { n },
+            (
+                &EventTree::Node {
+                    n,
+                    ref left,
+                    ref right,
+                },
+                base,
+            ) => {
+                let (base_n, base_left, base_right) = match base {
+                    Some(&EventTree::Node {
+                        n,
+                        ref left,
+                        ref right,
+                    }) => (Some(n), Some(&**left), Some(&**right)),
+                    _ => (None, None, None),
+                };
+                let left = EventDelta::new_delta(left, base_left);
+                let right = EventDelta::new_delta(right, base_right);
+                if base_n == Some(n) && left == EventDelta::Same && right == EventDelta::Same {
+                    EventDelta::Same
+                } else {
+                    EventDelta::Node {
+                        n,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the target tree from `base`, failing if the delta refers
+    /// to a base subtree that does not exist or nests deeper than
+    /// `MAX_DEPTH`.
+    pub fn apply(&self, base: Option<&EventTree<N>>) -> Result<EventTree<N>, Error> {
+        self.apply_at(base, 0)
+    }
+
+    // checks the depth on the way down, so a malicious delta is rejected
+    // before it is rebuilt
+    fn apply_at(&self, base: Option<&EventTree<N>>, depth: usize) -> Result<EventTree<N>, Error> {
+        match *self {
+            EventDelta::Same => base.cloned().ok_or(Error::DeltaMismatch),
+            EventDelta::Leaf { n } => Ok(EventTree::new_leaf(n)),
+            EventDelta::Node { .. } if depth == MAX_DEPTH => Err(Error::DepthLimitExceeded),
+            EventDelta::Node {
+                n,
+                ref left,
+                ref right,
+            } => {
+                let (base_left, base_right) = match base {
+                    Some(EventTree::Node { left, right, .. }) => (Some(&**left), Some(&**right)),
+                    _ => (None, None),
+                };
+                Ok(EventTree::new_node(
+                    n,
+                    Box::new(left.apply_at(base_left, depth + 1)?),
+                    Box::new(right.apply_at(base_right, depth + 1)?),
+                ))
+            }
+        }
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    /// Describes this stamp relative to `base`, for a peer that already
+    /// holds `base`.
+    pub fn delta_from(&self, base: &Stamp<N>) -> StampDelta<N> {
+        let i = if self.i == base.i {
+            None
+        } else {
+            Some(self.i.clone())
+        };
+        let delta = StampDelta::new(i, EventDelta::new_delta(&self.e, Some(&base.e)));
+        if self.epoch == base.epoch {
+            delta
+        } else {
+            delta.with_epoch(self.epoch)
+        }
+    }
+
+    /// Rebuilds the stamp a delta was taken of, with this stamp as its base.
+    ///
+    /// Fails if the delta refers to parts of the base that do not exist, or
+    /// the result is not a valid stamp, as happens when the delta was taken
+    /// from a different base.
+    pub fn apply_delta(&self, delta: &StampDelta<N>) -> Result<Stamp<N>, Error> {
+        let i = delta.i.as_ref().unwrap_or(&self.i).clone();
+        let e = delta.e.apply(Some(&self.e))?;
+        let epoch = delta.epoch.unwrap_or(self.epoch);
+        let stamp = Stamp { i, e, epoch };
+        stamp.validate()?;
+        Ok(stamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IntervalTreeClock;
+    use std::str::FromStr;
+
+    #[test]
+    fn delta_round_trip() {
+        let mut replicas = Stamp::seed().fork_n(8);
+        let mut known: Vec<Stamp> = replicas.clone();
+        for round in 0..50 {
+            let k = round % replicas.len();
+            replicas[k].event_mut();
+            if round % 3 == 0 {
+                let (msg, _) = replicas[(k + 3) % replicas.len()].peek();
+                replicas[k].join_mut(&msg);
+            }
+
+            let delta = replicas[k].delta_from(&known[k]);
+            assert_eq!(Ok(replicas[k].clone()), known[k].apply_delta(&delta));
+            known[k] = replicas[k].clone();
+        }
+
+        for s in &replicas {
+            assert!(s.delta_from(s).is_empty());
+            assert_eq!(Ok(s.clone()), s.apply_delta(&s.delta_from(s)));
+        }
+    }
+
+    #[test]
+    fn delta_only_spells_out_changes() {
+        let base: Stamp = Stamp::from_str("((0,1),(1,(0,2,0),3))").unwrap();
+
+        let s = base.event();
+        let delta = s.delta_from(&base);
+        assert_eq!(None, delta.id());
+        assert_eq!(
+            &EventDelta::Node {
+                n: 1,
+                left: Box::new(EventDelta::Same),
+                right: Box::new(EventDelta::Leaf { n: 4 }),
+            },
+            delta.events()
+        );
+
+        let (_, s) = base.fork();
+        assert_eq!(
+            StampDelta::new(Some(s.id().clone()), EventDelta::Same),
+            s.delta_from(&base)
+        );
+
+        // a subtree where the base has a leaf is spelled out in full
+        let target: Stamp = Stamp::from_str("(1,(0,(0,1,0),0))").unwrap();
+        let base = Stamp::from_str("(1,(0,0,1))").unwrap();
+        assert_eq!(
+            &EventDelta::Node {
+                n: 0,
+                left: Box::new(EventDelta::Node {
+                    n: 0,
+                    left: Box::new(EventDelta::Leaf { n: 1 }),
+                    right: Box::new(EventDelta::Leaf { n: 0 }),
+                }),
+                right: Box::new(EventDelta::Leaf { n: 0 }),
+            },
+            target.delta_from(&base).events()
+        );
+        assert_eq!(
+            Ok(target.clone()),
+            base.apply_delta(&target.delta_from(&base))
+        );
+    }
+
+    #[test]
+    fn delta_across_rebase() {
+        let base: Stamp = Stamp::from_str("((0,1),(1,(0,2,0),3))").unwrap();
+        let s = base.rebase(&EventTree::leaf(1)).event();
+        let delta = s.delta_from(&base);
+        assert_eq!(Some(1), delta.epoch());
+        assert_eq!(Ok(s.clone()), base.apply_delta(&delta));
+        assert_eq!(None, s.event().delta_from(&s).epoch());
+    }
+
+    #[test]
+    fn delta_against_wrong_base() {
+        let base: Stamp = Stamp::from_str("(1,(0,(0,1,0),0))").unwrap();
+        let target = Stamp::from_str("(1,(0,(0,2,0),0))").unwrap();
+        let delta = target.delta_from(&base);
+
+        assert_eq!(Err(Error::DeltaMismatch), Stamp::seed().apply_delta(&delta));
+        let other = Stamp::from_str("(1,(0,(0,0,2),0))").unwrap();
+        assert_eq!(Err(Error::NonNormalEventTree), other.apply_delta(&delta));
+    }
+
+    #[test]
+    fn delta_depth_limit() {
+        let nested = |depth| {
+            let mut e = EventDelta::Leaf { n: 1 };
+            for _ in 0..depth {
+                e = EventDelta::Node {
+                    n: 0,
+                    left: Box::new(e),
+                    right: Box::new(EventDelta::Leaf { n: 0 }),
+                };
+            }
+            StampDelta::new(None, e)
+        };
+        let s = Stamp::seed().apply_delta(&nested(MAX_DEPTH)).unwrap();
+        assert_eq!(MAX_DEPTH, s.events().depth());
+        assert_eq!(
+            Err(Error::DepthLimitExceeded),
+            Stamp::seed().apply_delta(&nested(MAX_DEPTH + 1))
+        );
+        assert_eq!(
+            Err(Error::DepthLimitExceeded),
+            nested(MAX_DEPTH + 1).events().apply(None)
+        );
+    }
+}
@@ -0,0 +1,618 @@
+//! Exact dyadic rationals, and the interval views of id and event trees
+//! they give.
+//!
+//! A tree node at depth `d` covers a sub-interval of `[0, 1)` of width
+//! `2^-d`, so every boundary a tree draws is a dyadic rational `num / 2^exp`.
+//! `Dyadic` keeps these exact where floats would round. Exponents are limited
+//! to `Dyadic::MAX_EXP`, which covers every tree within `MAX_DEPTH`; the
+//! views here fail with `DepthLimitExceeded` on trees deeper than that.
+
+use std::cmp;
+use std::fmt;
+use std::ops::{Add, RangeInclusive};
+
+use crate::counter::Counter;
+use crate::error::Error;
+use crate::overflow::OverflowPolicy;
+use crate::{EventTree, IdTree};
+
+/// A non-negative rational `num / 2^exp`, kept in lowest terms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dyadic {
+    num: u128,
+    exp: u32,
+}
+
+impl Dyadic {
+    pub const MAX_EXP: u32 = 127;
+
+    /// Panics if `exp` exceeds `MAX_EXP` once the fraction is reduced.
+    pub fn new(num: u128, exp: u32) -> Dyadic {
+        let shift = cmp::min(num.trailing_zeros(), exp);
+        let (num, exp) = (num >> shift, exp - shift);
+        assert!(
+            exp <= Dyadic::MAX_EXP,
+            "dyadic exponent exceeds {}",
+            Dyadic::MAX_EXP
+        );
+        Dyadic { num, exp }
+    }
+
+    pub fn zero() -> Dyadic {
+        Dyadic { num: 0, exp: 0 }
+    }
+
+    pub fn one() -> Dyadic {
+        Dyadic { num: 1, exp: 0 }
+    }
+
+    pub fn numerator(&self) -> u128 {
+        self.num
+    }
+
+    pub fn exponent(&self) -> u32 {
+        self.exp
+    }
+
+    pub fn half(self) -> Dyadic {
+        Dyadic::new(self.num, self.exp + 1)
+    }
+
+    /// Like `half`, but `None` if the exponent would exceed `MAX_EXP`.
+    pub fn checked_half(self) -> Option<Dyadic> {
+        if self.num != 0 && self.exp == Dyadic::MAX_EXP {
+            None
+        } else {
+            Some(self.half())
+        }
+    }
+
+    pub fn checked_add(self, other: Dyadic) -> Option<Dyadic> {
+        let exp = cmp::max(self.exp, other.exp);
+        let sum = self.num_at(exp)?.checked_add(other.num_at(exp)?)?;
+        Some(Dyadic::new(sum, exp))
+    }
+
+    pub fn checked_sub(self, other: Dyadic) -> Option<Dyadic> {
+        let exp = cmp::max(self.exp, other.exp);
+        let difference = self.num_at(exp)?.checked_sub(other.num_at(exp)?)?;
+        Some(Dyadic::new(difference, exp))
+    }
+
+    pub fn checked_mul(self, k: u128) -> Option<Dyadic> {
+        Some(Dyadic::new(self.num.checked_mul(k)?, self.exp))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / 2f64.powi(self.exp as i32)
+    }
+
+    // the numerator over 2^exp, for exp >= self.exp
+    fn num_at(self, exp: u32) -> Option<u128> {
+        let shift = exp - self.exp;
+        if shift > 0 && self.num.leading_zeros() < shift {
+            None
+        } else {
+            Some(self.num << shift)
+        }
+    }
+}
+
+/// Panics if the sum does not fit.
+impl Add for Dyadic {
+    type Output = Dyadic;
+
+    fn add(self, other: Dyadic) -> Dyadic {
+        self.checked_add(other).expect("dyadic overflow")
+    }
+}
+
+impl Ord for Dyadic {
+    fn cmp(&self, other: &Dyadic) -> cmp::Ordering {
+        // whole parts first, then fractions, which always fit at the larger exponent
+        let whole = |d: &Dyadic| d.num >> d.exp;
+        let fraction = |d: &Dyadic| d.num & ((1 << d.exp) - 1);
+        let exp = cmp::max(self.exp, other.exp);
+        whole(self).cmp(&whole(other)).then_with(|| {
+            (fraction(self) << (exp - self.exp)).cmp(&(fraction(other) << (exp - other.exp)))
+        })
+    }
+}
+
+impl PartialOrd for Dyadic {
+    fn partial_cmp(&self, other: &Dyadic) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Dyadic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.exp == 0 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, 1u128 << self.exp)
+        }
+    }
+}
+
+impl IdTree {
+    /// The owned sub-intervals of `[0, 1)` as `(start, end)` pairs, in order
+    /// and with adjacent intervals merged.
+    ///
+    /// Fails if the tree is deeper than `Dyadic::MAX_EXP`.
+    pub fn intervals(&self) -> Result<impl Iterator<Item = (Dyadic, Dyadic)>, Error> {
+        let mut intervals: Vec<(Dyadic, Dyadic)> = Vec::new();
+        let mut stack = vec![(self, Dyadic::zero(), 0)];
+        while let Some((tree, start, depth)) = stack.pop() {
+            match *tree {
+                IdTree::Leaf { i: false } => {}
+                IdTree::Leaf { i: true } => {
+                    let end = start + Dyadic::new(1, depth);
+                    match intervals.last_mut() {
+                        Some(last) if last.1 == start => last.1 = end,
+                        _ => intervals.push((start, end)),
+                    }
+                }
+                IdTree::Node { .. } if depth == Dyadic::MAX_EXP => {
+                    return Err(Error::DepthLimitExceeded);
+                }
+                IdTree::Node {
+                    ref left,
+                    ref right,
+                } => {
+                    let middle = start + Dyadic::new(1, depth + 1);
+                    stack.push((right, middle, depth + 1));
+                    stack.push((left, start, depth + 1));
+                }
+            }
+        }
+        Ok(intervals.into_iter())
+    }
+
+    /// The normalised id owning the union of `intervals`.
+    ///
+    /// Fails if an interval ends before it starts or extends past 1.
+    pub fn from_intervals<I>(intervals: I) -> Result<IdTree, Error>
+    where
+        I: IntoIterator<Item = (Dyadic, Dyadic)>,
+    {
+        let mut i = IdTree::zero();
+        for (start, end) in intervals {
+            if start > end || end > Dyadic::one() {
+                return Err(Error::InvalidInterval);
+            }
+            let exp = cmp::max(start.exp, end.exp);
+            // both ends are at most 1, so these fit
+            let range = IdTree::range(
+                start.num << (exp - start.exp),
+                end.num << (exp - end.exp),
+                exp as usize,
+            );
+            i = i.combine(&range, &|a, b| a || b);
+        }
+        Ok(i)
+    }
+
+    /// The fraction of `[0, 1)` the id owns.
+    ///
+    /// Fails if the tree is deeper than `Dyadic::MAX_EXP`.
+    pub fn measure(&self) -> Result<Dyadic, Error> {
+        // the intervals are disjoint within [0, 1), so neither step overflows
+        Ok(self.intervals()?.fold(Dyadic::zero(), |m, (start, end)| {
+            m + end.checked_sub(start).expect("intervals are ordered")
+        }))
+    }
+}
+
+impl<N: Counter> EventTree<N> {
+    /// The tree as a step function over `[0, 1)`: `((start, end), count)`
+    /// pairs in order, with adjacent segments of equal count merged.
+    ///
+    /// Fails if a count does not fit in `N`, or the tree is deeper than
+    /// `Dyadic::MAX_EXP`.
+    pub fn segments(&self) -> Result<impl Iterator<Item = ((Dyadic, Dyadic), N)>, Error> {
+        let mut segments: Vec<((Dyadic, Dyadic), N)> = Vec::new();
+        let mut stack = vec![(self, N::ZERO, Dyadic::zero(), 0)];
+        while let Some((tree, base, start, depth)) = stack.pop() {
+            let count = OverflowPolicy::Error.add(base, tree.n())?;
+            match *tree {
+                EventTree::Leaf { .. } => {
+                    let end = start + Dyadic::new(1, depth);
+                    match segments.last_mut() {
+                        Some(last) if last.0 .1 == start && last.1 == count => last.0 .1 = end,
+                        _ => segments.push(((start, end), count)),
+                    }
+                }
+                EventTree::Node { .. } if depth == Dyadic::MAX_EXP => {
+                    return Err(Error::DepthLimitExceeded);
+                }
+                EventTree::Node {
+                    ref left,
+                    ref right,
+                    ..
+                } => {
+                    let middle = start + Dyadic::new(1, depth + 1);
+                    stack.push((right, count, middle, depth + 1));
+                    stack.push((left, count, start, depth + 1));
+                }
+            }
+        }
+        Ok(segments.into_iter())
+    }
+
+    /// The count the tree records at `point`.
+    ///
+    /// Fails with `InvalidInterval` if `point` is not within `[0, 1)`, or
+    /// `CounterOverflow` if the count does not fit in `N`.
+    pub fn value_at(&self, point: Dyadic) -> Result<N, Error> {
+        if point >= Dyadic::one() {
+            return Err(Error::InvalidInterval);
+        }
+        let mut tree = self;
+        let mut count = N::ZERO;
+        let mut start = Dyadic::zero();
+        let mut depth = 0;
+        loop {
+            count = OverflowPolicy::Error.add(count, tree.n())?;
+            match *tree {
+                EventTree::Leaf { .. } => return Ok(count),
+                EventTree::Node { ref left, .. } if depth >= point.exp => {
+                    // finer than the point itself, which starts every node from here
+                    tree = left;
+                }
+                EventTree::Node {
+                    ref left,
+                    ref right,
+                    ..
+                } => {
+                    depth += 1;
+                    let middle = start + Dyadic::new(1, depth);
+                    if point < middle {
+                        tree = left;
+                    } else {
+                        tree = right;
+                        start = middle;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The area under the step function, i.e. the number of events seen,
+    /// weighted by the share of the id space that recorded them.
+    ///
+    /// Fails like `segments`, or with `CounterOverflow` if the area does
+    /// not fit in a `Dyadic`.
+    pub fn integral(&self) -> Result<Dyadic, Error> {
+        self.segments()?
+            .try_fold(Dyadic::zero(), |area, ((start, end), count)| {
+                let width = end.checked_sub(start).expect("segments are ordered");
+                width
+                    .checked_mul(count.to_u64() as u128)
+                    .and_then(|part| area.checked_add(part))
+                    .ok_or(Error::CounterOverflow)
+            })
+    }
+
+    /// The events this tree has seen that `other` has not, as the range of
+    /// counts `other` is missing over each sub-interval, in order.
+    ///
+    /// Fails like `segments` on either tree.
+    pub fn difference(
+        &self,
+        other: &EventTree<N>,
+    ) -> Result<impl Iterator<Item = ((Dyadic, Dyadic), RangeInclusive<N>)>, Error> {
+        let mut missing: Vec<((Dyadic, Dyadic), RangeInclusive<N>)> = Vec::new();
+        let mut mine = self.segments()?.peekable();
+        let mut theirs = other.segments()?.peekable();
+        let mut start = Dyadic::zero();
+        while let (Some(&((_, end1), n1)), Some(&((_, end2), n2))) = (mine.peek(), theirs.peek()) {
+            let end = cmp::min(end1, end2);
+            if n1 > n2 {
+                // cannot overflow, as n2 < n1
+                missing.push(((start, end), n2.checked_add(N::ONE).unwrap()..=n1));
+            }
+            if end1 == end {
+                mine.next();
+            }
+            if end2 == end {
+                theirs.next();
+            }
+            start = end;
+        }
+        Ok(missing.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IntervalTreeClock, LessThanOrEqual, Stamp, MAX_DEPTH};
+    use std::str::FromStr;
+
+    fn d(num: u128, exp: u32) -> Dyadic {
+        Dyadic::new(num, exp)
+    }
+
+    #[test]
+    fn dyadic_arithmetic() {
+        assert_eq!(d(1, 1), d(4, 3));
+        assert_eq!((1, 1), (d(4, 3).numerator(), d(4, 3).exponent()));
+        assert_eq!(Dyadic::zero(), d(0, 9));
+        assert_eq!(d(7, 3), d(1, 1) + d(3, 3));
+        assert_eq!(Dyadic::one(), d(1, 1) + d(1, 1));
+        assert_eq!(d(1, 2), d(1, 1).half());
+        assert_eq!(Some(d(1, 127)), d(1, 126).checked_half());
+        assert_eq!(None, d(1, 127).checked_half());
+        assert_eq!(Some(Dyadic::zero()), Dyadic::zero().checked_half());
+        assert!(d(3, 3) < d(1, 1));
+        assert!(d(5, 1) > d(9, 2));
+        assert!(d(1, 127) > Dyadic::zero());
+        assert_eq!("3/8", d(3, 3).to_string());
+        assert_eq!("2", d(4, 1).to_string());
+        assert_eq!(0.375, d(3, 3).to_f64());
+        assert_eq!(None, d(u128::MAX, 0).checked_add(d(1, 1)));
+        assert_eq!(Some(d(1, 3)), d(1, 1).checked_sub(d(3, 3)));
+        assert_eq!(None, d(3, 3).checked_sub(d(1, 1)));
+        assert_eq!(Some(d(15, 3)), d(5, 3).checked_mul(3));
+    }
+
+    #[test]
+    fn intervals() {
+        let id = |s: &str| IdTree::from_str(s).unwrap();
+
+        assert_eq!(0, id("0").intervals().unwrap().count());
+        assert_eq!(
+            vec![(d(0, 0), d(1, 0))],
+            id("1").intervals().unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(d(0, 0), d(3, 2))],
+            id("(1,(1,0))").intervals().unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(d(1, 3), d(1, 1)), (d(3, 2), d(1, 0))],
+            id("(((0,1),1),(0,1))")
+                .intervals()
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+
+        for s in [
+            "0",
+            "1",
+            "(1,0)",
+            "(0,(1,0))",
+            "(((0,1),1),(0,1))",
+            "((1,(0,1)),(1,0))",
+        ] {
+            let i = id(s);
+            assert_eq!(
+                Ok(i.clone()),
+                IdTree::from_intervals(i.intervals().unwrap()),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn from_intervals() {
+        assert_eq!(
+            IdTree::from_str("((0,1),(1,0))"),
+            Ok(IdTree::from_intervals([(d(1, 2), d(3, 2))]).unwrap())
+        );
+        // overlapping intervals are merged
+        assert_eq!(
+            Ok(IdTree::from_str("(1,(1,0))").unwrap()),
+            IdTree::from_intervals([(d(0, 0), d(1, 1)), (d(1, 2), d(3, 2))])
+        );
+        assert_eq!(
+            Ok(IdTree::zero()),
+            IdTree::from_intervals([(d(1, 1), d(1, 1))])
+        );
+        assert_eq!(
+            Err(Error::InvalidInterval),
+            IdTree::from_intervals([(d(1, 1), d(1, 2))])
+        );
+        assert_eq!(
+            Err(Error::InvalidInterval),
+            IdTree::from_intervals([(d(1, 1), d(3, 1))])
+        );
+    }
+
+    #[test]
+    fn measure() {
+        assert_eq!(Ok(Dyadic::zero()), IdTree::zero().measure());
+        assert_eq!(Ok(Dyadic::one()), IdTree::one().measure());
+        assert_eq!(
+            Ok(d(5, 3)),
+            IdTree::from_str("((0,1),(1,(0,1)))").unwrap().measure()
+        );
+
+        let replicas = Stamp::seed().fork_n(3);
+        let total = replicas
+            .iter()
+            .fold(Dyadic::zero(), |m, s| m + s.id().measure().unwrap());
+        assert_eq!(Dyadic::one(), total);
+    }
+
+    #[test]
+    fn deep_ids() {
+        let deep = |depth| {
+            let mut i = IdTree::one();
+            for _ in 0..depth {
+                i = IdTree::node(Box::new(IdTree::zero()), Box::new(i));
+            }
+            i
+        };
+        // the smallest leaf a valid id can have still fits
+        let i = deep(MAX_DEPTH);
+        let start = d((1 << MAX_DEPTH) - 1, MAX_DEPTH as u32);
+        assert_eq!(
+            vec![(start, Dyadic::one())],
+            i.intervals().unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(Ok(d(1, MAX_DEPTH as u32)), i.measure());
+        assert_eq!(Ok(i), IdTree::from_intervals([(start, Dyadic::one())]));
+
+        let i = deep(MAX_DEPTH + 1);
+        assert_eq!(Err(Error::DepthLimitExceeded), i.intervals().map(|_| ()));
+        assert_eq!(Err(Error::DepthLimitExceeded), i.measure());
+    }
+
+    #[test]
+    fn segments() {
+        let e = |s: &str| EventTree::<u32>::from_str(s).unwrap();
+
+        assert_eq!(
+            vec![((d(0, 0), d(1, 0)), 0)],
+            EventTree::zero().segments().unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                ((d(0, 0), d(1, 1)), 1),
+                ((d(1, 1), d(3, 2)), 4),
+                ((d(3, 2), d(1, 0)), 3)
+            ],
+            e("(1,0,(1,2,1))").segments().unwrap().collect::<Vec<_>>()
+        );
+        // equal neighbours across subtrees are merged
+        assert_eq!(
+            vec![
+                ((d(0, 0), d(1, 2)), 0),
+                ((d(1, 2), d(3, 2)), 2),
+                ((d(3, 2), d(1, 0)), 0)
+            ],
+            e("(0,(0,0,2),(0,2,0))")
+                .segments()
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn value_at() {
+        let e: EventTree = EventTree::from_str("(1,0,(1,2,1))").unwrap();
+        assert_eq!(Ok(1), e.value_at(Dyadic::zero()));
+        assert_eq!(Ok(1), e.value_at(d(3, 3)));
+        assert_eq!(Ok(4), e.value_at(d(1, 1)));
+        assert_eq!(Ok(4), e.value_at(d(5, 3)));
+        assert_eq!(Ok(3), e.value_at(d(3, 2)));
+        assert_eq!(Ok(3), e.value_at(d(127, 7)));
+
+        for ((start, _), count) in e.segments().unwrap() {
+            assert_eq!(Ok(count), e.value_at(start));
+        }
+    }
+
+    #[test]
+    fn value_at_out_of_range() {
+        assert_eq!(
+            Err(Error::InvalidInterval),
+            EventTree::<u32>::zero().value_at(Dyadic::one())
+        );
+    }
+
+    #[test]
+    fn counts_past_counter_max() {
+        let e: EventTree = EventTree::from_str("(4294967295,1,0)").unwrap();
+        assert_eq!(Err(Error::CounterOverflow), e.segments().map(|_| ()));
+        assert_eq!(Err(Error::CounterOverflow), e.value_at(Dyadic::zero()));
+        assert_eq!(Ok(u32::MAX), e.value_at(d(1, 1)));
+        assert_eq!(Err(Error::CounterOverflow), e.integral());
+        assert_eq!(
+            Err(Error::CounterOverflow),
+            e.difference(&EventTree::zero()).map(|_| ())
+        );
+
+        // the area of a finely bounded segment with a large count does not fit
+        let mut deep = EventTree::leaf(0);
+        for _ in 0..MAX_DEPTH {
+            deep = EventTree::node(0, Box::new(EventTree::leaf(u32::MAX)), Box::new(deep));
+        }
+        assert_eq!(2, deep.segments().unwrap().count());
+        assert_eq!(Err(Error::CounterOverflow), deep.integral());
+    }
+
+    #[test]
+    fn deep_events() {
+        let mut e = EventTree::<u32>::leaf(1);
+        for _ in 0..MAX_DEPTH {
+            e = EventTree::node(0, Box::new(EventTree::zero()), Box::new(e));
+        }
+        let start = d((1 << MAX_DEPTH) - 1, MAX_DEPTH as u32);
+        assert_eq!(
+            vec![((Dyadic::zero(), start), 0), ((start, Dyadic::one()), 1)],
+            e.segments().unwrap().collect::<Vec<_>>()
+        );
+        assert_eq!(Ok(d(1, MAX_DEPTH as u32)), e.integral());
+
+        let e = EventTree::node(0, Box::new(e), Box::new(EventTree::zero()));
+        assert_eq!(Err(Error::DepthLimitExceeded), e.segments().map(|_| ()));
+        assert_eq!(Err(Error::DepthLimitExceeded), e.integral());
+        // points need no finer dyadics than their own, even down the deepest node
+        assert_eq!(Ok(0), e.value_at(Dyadic::zero()));
+        assert_eq!(Ok(0), e.value_at(d(1, 1)));
+        assert_eq!(Ok(0), e.value_at(d((1 << 126) - 1, 127)));
+    }
+
+    #[test]
+    fn integral() {
+        assert_eq!(Ok(Dyadic::zero()), EventTree::<u32>::zero().integral());
+        assert_eq!(Ok(d(5, 0)), EventTree::leaf(5).integral());
+        // 1/2 * 1 + 1/4 * 4 + 1/4 * 3
+        assert_eq!(
+            Ok(d(9, 2)),
+            EventTree::<u32>::from_str("(1,0,(1,2,1))")
+                .unwrap()
+                .integral()
+        );
+
+        // one event per replica adds its share of the id space
+        let mut s = Stamp::seed();
+        let (mut a, b) = s.fork();
+        a = a.event();
+        s = a.join(&b.event());
+        assert_eq!(Ok(Dyadic::one()), s.events().integral());
+    }
+
+    #[test]
+    fn difference() {
+        let e = |s: &str| EventTree::<u32>::from_str(s).unwrap();
+        let missing = |a: &str, b: &str| e(a).difference(&e(b)).unwrap().collect::<Vec<_>>();
+
+        assert_eq!(vec![((d(0, 0), d(1, 0)), 1..=3)], missing("3", "0"));
+        assert_eq!(
+            vec![((d(1, 1), d(3, 2)), 2..=4), ((d(3, 2), d(1, 0)), 3..=3)],
+            missing("(1,0,(1,2,1))", "(0,1,(0,1,2))")
+        );
+        assert_eq!(
+            vec![((d(1, 2), d(1, 1)), 2..=2), ((d(3, 2), d(1, 0)), 2..=2)],
+            missing("(0,(0,0,2),2)", "(1,0,(0,1,0))")
+        );
+        assert_eq!(0, missing("(1,0,2)", "(1,1,3)").len());
+
+        let mut stamps = Stamp::seed().fork_n(4);
+        for k in 0..20 {
+            stamps[k % 4].event_mut();
+            if k % 3 == 0 {
+                let (msg, _) = stamps[(k + 1) % 4].peek();
+                stamps[k % 4].join_mut(&msg);
+            }
+        }
+        for a in &stamps {
+            for b in &stamps {
+                let (a, b) = (a.events(), b.events());
+                let gaps: Vec<_> = a.difference(b).unwrap().collect();
+                assert_eq!(gaps.is_empty(), a.leq(b));
+                for ((start, _), counts) in gaps {
+                    assert_eq!(Ok(*counts.start()), b.value_at(start).map(|n| n + 1));
+                    assert_eq!(Ok(*counts.end()), a.value_at(start));
+                }
+                assert_eq!(0, b.join(a).difference(&b.join(a)).unwrap().count());
+                assert_eq!(0, b.difference(&b.join(a)).unwrap().count());
+            }
+        }
+    }
+}
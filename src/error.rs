@@ -0,0 +1,51 @@
+use std::error;
+use std::fmt;
+
+/// Errors returned by the fallible clock operations.
+///
+/// Each variant names the invariant that a tree or stamp failed to uphold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// An id tree node has two equal leaves, e.g. `(1,1)`.
+    NonNormalIdTree,
+    /// An event tree node has equal leaves, or neither child has a zero base.
+    NonNormalEventTree,
+    /// Two ids being joined both own some part of the interval.
+    IdOverlap,
+    /// An event or fork was attempted on a stamp that owns no part of the interval.
+    AnonymousStamp,
+    /// An event counter would exceed its maximum value.
+    CounterOverflow,
+    /// An event counter would drop below zero.
+    CounterUnderflow,
+    /// A tree is nested deeper than `MAX_DEPTH`, or the limit given instead.
+    DepthLimitExceeded,
+    /// An interval ends before it starts or extends past 1.
+    InvalidInterval,
+    /// A delta refers to parts of its base that do not exist.
+    DeltaMismatch,
+    /// Stamps from different epochs were joined; see `Stamp::rebase`.
+    EpochMismatch,
+    /// A fork into no stamps, or by weights that sum to zero.
+    EmptyFork,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NonNormalIdTree => write!(f, "id tree is not normalised"),
+            Error::NonNormalEventTree => write!(f, "event tree is not normalised"),
+            Error::IdOverlap => write!(f, "ids overlap"),
+            Error::AnonymousStamp => write!(f, "stamp has an anonymous id"),
+            Error::CounterOverflow => write!(f, "event counter overflow"),
+            Error::CounterUnderflow => write!(f, "event counter underflow"),
+            Error::DepthLimitExceeded => write!(f, "tree exceeds the depth limit"),
+            Error::InvalidInterval => write!(f, "interval is not within [0, 1)"),
+            Error::DeltaMismatch => write!(f, "delta does not match its base"),
+            Error::EpochMismatch => write!(f, "stamps are from different epochs"),
+            Error::EmptyFork => write!(f, "fork into no stamps"),
+        }
+    }
+}
+
+impl error::Error for Error {}
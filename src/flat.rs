@@ -0,0 +1,1008 @@
+//! A flat representation of id trees, event trees and stamps.
+//!
+//! Each tree is stored as its nodes in preorder in a single `Vec`, so a stamp
+//! is two vectors rather than a `Box` per node. The clock verbs read their
+//! inputs in place and write each result tree into one new vector, so they
+//! allocate once per output tree instead of once per visited node.
+//!
+//! `FlatStamp` implements `IntervalTreeClock` and converts to and from `Stamp`,
+//! keeping its epoch. As on `Stamp`, `try_join`, `try_event` and `event_with`
+//! return errors instead of panicking.
+
+use std::cmp;
+
+use crate::cost::{GrowSide, GrowStrategy, MinimalGrowth};
+use crate::counter::Counter;
+use crate::error::Error;
+use crate::overflow::OverflowPolicy;
+use crate::{
+    lift_wide, Causality, EventTree, IdTree, IntervalTreeClock, LessThanOrEqual, Stamp, MAX_DEPTH,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdNode {
+    Leaf(bool),
+    Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventNode<N = u32> {
+    Leaf(N),
+    Node(N),
+}
+
+impl<N: Counter> EventNode<N> {
+    pub fn n(self) -> N {
+        match self {
+            EventNode::Leaf(n) => n,
+            EventNode::Node(n) => n,
+        }
+    }
+
+    fn with_n(self, n: N) -> EventNode<N> {
+        match self {
+            EventNode::Leaf(_) => EventNode::Leaf(n),
+            EventNode::Node(_) => EventNode::Node(n),
+        }
+    }
+}
+
+/// An id tree stored as its nodes in preorder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatIdTree {
+    nodes: Vec<IdNode>,
+}
+
+/// An event tree stored as its nodes in preorder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatEventTree<N = u32> {
+    nodes: Vec<EventNode<N>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatStamp<N = u32> {
+    i: FlatIdTree,
+    e: FlatEventTree<N>,
+    // as for `Stamp`, stamps of different epochs are incomparable
+    epoch: u32,
+}
+
+impl FlatIdTree {
+    pub fn zero() -> FlatIdTree {
+        FlatIdTree {
+            nodes: vec![IdNode::Leaf(false)],
+        }
+    }
+
+    pub fn one() -> FlatIdTree {
+        FlatIdTree {
+            nodes: vec![IdNode::Leaf(true)],
+        }
+    }
+
+    pub fn nodes(&self) -> &[IdNode] {
+        &self.nodes
+    }
+
+    /// Checks that the tree is in normal form and no deeper than `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        // the children still to visit of each node above the current one
+        let mut pending: Vec<u8> = Vec::new();
+        for (k, node) in self.nodes.iter().enumerate() {
+            if pending.len() > max_depth {
+                return Err(Error::DepthLimitExceeded);
+            }
+            match node {
+                IdNode::Node => {
+                    if let [IdNode::Leaf(i1), IdNode::Leaf(i2)] = self.nodes[k + 1..k + 3] {
+                        if i1 == i2 {
+                            return Err(Error::NonNormalIdTree);
+                        }
+                    }
+                    pending.push(2);
+                }
+                IdNode::Leaf(_) => {
+                    while let Some(children) = pending.last_mut() {
+                        *children -= 1;
+                        if *children > 0 {
+                            break;
+                        }
+                        pending.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn try_sum(&self, other: &FlatIdTree) -> Result<FlatIdTree, Error> {
+        let mut nodes = Vec::with_capacity(self.nodes.len() + other.nodes.len());
+        try_sum_into(&self.nodes, 0, &other.nodes, 0, &mut nodes)?;
+        Ok(FlatIdTree { nodes })
+    }
+}
+
+impl<N: Counter> FlatEventTree<N> {
+    pub fn new_zero() -> FlatEventTree<N> {
+        FlatEventTree {
+            nodes: vec![EventNode::Leaf(N::ZERO)],
+        }
+    }
+
+    pub fn nodes(&self) -> &[EventNode<N>] {
+        &self.nodes
+    }
+
+    /// Checks that the tree is in normal form and no deeper than `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        // for each node above the current one, its children started so far
+        // and the least of their counters
+        let mut stack: Vec<(u8, N)> = Vec::new();
+        for (k, node) in self.nodes.iter().enumerate() {
+            if stack.len() > max_depth {
+                return Err(Error::DepthLimitExceeded);
+            }
+            if let Some((started, m)) = stack.last_mut() {
+                *m = if *started == 0 {
+                    node.n()
+                } else {
+                    cmp::min(*m, node.n())
+                };
+                *started += 1;
+                if *started == 2 && *m != N::ZERO {
+                    return Err(Error::NonNormalEventTree);
+                }
+            }
+            match node {
+                EventNode::Node(_) => {
+                    if let [EventNode::Leaf(m1), EventNode::Leaf(m2)] = self.nodes[k + 1..k + 3] {
+                        if m1 == m2 {
+                            return Err(Error::NonNormalEventTree);
+                        }
+                    }
+                    stack.push((0, N::ZERO));
+                }
+                EventNode::Leaf(_) => {
+                    while let Some((2, _)) = stack.last() {
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Panics if a counter overflows.
+    pub fn join(&self, other: &FlatEventTree<N>) -> FlatEventTree<N> {
+        self.join_with(other, OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+
+    /// Like `join`, but applies `policy` if a counter overflows.
+    pub fn join_with(
+        &self,
+        other: &FlatEventTree<N>,
+        policy: OverflowPolicy,
+    ) -> Result<FlatEventTree<N>, Error> {
+        let mut nodes = Vec::with_capacity(cmp::max(self.nodes.len(), other.nodes.len()));
+        join_into(
+            (&self.nodes, 0),
+            N::ZERO,
+            (&other.nodes, 0),
+            N::ZERO,
+            policy,
+            &mut nodes,
+        )?;
+        Ok(FlatEventTree { nodes })
+    }
+
+    /// Compares two event histories without allocating.
+    pub fn causality(&self, other: &FlatEventTree<N>) -> Causality {
+        let (leq, geq) = compare((&self.nodes, 0), 0, (&other.nodes, 0), 0);
+        Causality::from_leq(leq, geq)
+    }
+}
+
+impl<N: Counter> LessThanOrEqual for FlatEventTree<N> {
+    fn leq(&self, other: &FlatEventTree<N>) -> bool {
+        compare((&self.nodes, 0), 0, (&other.nodes, 0), 0).0
+    }
+}
+
+impl FlatStamp {
+    pub fn seed() -> FlatStamp {
+        FlatStamp::new_seed()
+    }
+}
+
+impl<N: Counter> FlatStamp<N> {
+    /// Like `seed`, for any counter type.
+    pub fn new_seed() -> FlatStamp<N> {
+        FlatStamp::new(FlatIdTree::one(), FlatEventTree::new_zero())
+    }
+
+    pub fn new(i: FlatIdTree, e: FlatEventTree<N>) -> FlatStamp<N> {
+        FlatStamp { i, e, epoch: 0 }
+    }
+
+    // a stamp in the same epoch as this one
+    fn with_parts(&self, i: FlatIdTree, e: FlatEventTree<N>) -> FlatStamp<N> {
+        FlatStamp {
+            i,
+            e,
+            epoch: self.epoch,
+        }
+    }
+
+    pub fn id_tree(&self) -> &FlatIdTree {
+        &self.i
+    }
+
+    pub fn event_tree(&self) -> &FlatEventTree<N> {
+        &self.e
+    }
+
+    /// How many times the stamp this was converted from had been rebased.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Stamps from different epochs are `Concurrent`, as for `Stamp`.
+    pub fn causality(&self, other: &FlatStamp<N>) -> Causality {
+        if self.epoch != other.epoch {
+            return Causality::Concurrent;
+        }
+        self.e.causality(&other.e)
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.i.nodes == [IdNode::Leaf(false)]
+    }
+
+    /// Checks that both trees are in normal form and within `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        self.i.validate_within(max_depth)?;
+        self.e.validate_within(max_depth)
+    }
+
+    /// Like `join`, but fails instead of panicking on invalid or overlapping
+    /// stamps, or stamps from different epochs.
+    pub fn try_join(&self, other: &FlatStamp<N>) -> Result<FlatStamp<N>, Error> {
+        self.validate()?;
+        other.validate()?;
+        if self.epoch != other.epoch {
+            return Err(Error::EpochMismatch);
+        }
+        let sum_i = self.i.try_sum(&other.i)?;
+        let join_e = self.e.join_with(&other.e, OverflowPolicy::Error)?;
+        Ok(self.with_parts(sum_i, join_e))
+    }
+
+    /// Like `event`, but fails instead of panicking on an invalid or anonymous
+    /// stamp, or when the counter would overflow.
+    pub fn try_event(&self) -> Result<FlatStamp<N>, Error> {
+        self.validate()?;
+        self.event_with(OverflowPolicy::Error)
+    }
+
+    /// Like `event`, but applies `policy` if the counter overflows.
+    pub fn event_with(&self, policy: OverflowPolicy) -> Result<FlatStamp<N>, Error> {
+        self.event_with_strategy(&MinimalGrowth, policy)
+    }
+
+    /// Like `event_with`, but lets `strategy` pick which subtree to grow
+    /// when no counter can be filled instead.
+    pub fn event_with_strategy(
+        &self,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<FlatStamp<N>, Error> {
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+
+        let filled_e = self.fill(policy)?;
+
+        if filled_e != self.e {
+            Ok(self.with_parts(self.i.clone(), filled_e))
+        } else {
+            Ok(self.with_parts(self.i.clone(), self.grow(strategy, policy)?))
+        }
+    }
+
+    fn fill(&self, policy: OverflowPolicy) -> Result<FlatEventTree<N>, Error> {
+        let mut nodes = Vec::with_capacity(self.e.nodes.len());
+        fill_into(&self.i.nodes, 0, (&self.e.nodes, 0), policy, &mut nodes)?;
+        Ok(FlatEventTree { nodes })
+    }
+
+    fn grow(
+        &self,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<FlatEventTree<N>, Error> {
+        let mut grow_left = vec![false; self.i.nodes.len()];
+        grow_plan(
+            &self.i.nodes,
+            0,
+            (&self.e.nodes, 0),
+            strategy,
+            &mut grow_left,
+        )?;
+        let mut nodes = Vec::with_capacity(self.e.nodes.len() + 2);
+        let ev = (&self.e.nodes[..], 0);
+        if grow_into(
+            &self.i.nodes,
+            0,
+            ev,
+            N::ZERO,
+            &grow_left,
+            policy,
+            &mut nodes,
+        )? {
+            Ok(FlatEventTree { nodes })
+        } else {
+            // a saturated counter does not grow, and undoing the splits
+            // above it leaves the tree as it was
+            Ok(self.e.clone())
+        }
+    }
+}
+
+/// Stamps from different epochs are never ordered.
+impl<N: Counter> LessThanOrEqual for FlatStamp<N> {
+    fn leq(&self, other: &FlatStamp<N>) -> bool {
+        self.epoch == other.epoch && self.e.leq(&other.e)
+    }
+}
+
+impl<N: Counter> IntervalTreeClock for FlatStamp<N> {
+    fn peek(&self) -> (FlatStamp<N>, FlatStamp<N>) {
+        let s1 = self.with_parts(FlatIdTree::zero(), self.e.clone());
+        (s1, self.clone())
+    }
+
+    /// Panics if the stamp is anonymous.
+    fn fork(&self) -> (FlatStamp<N>, FlatStamp<N>) {
+        if self.is_anonymous() {
+            panic!("{}", Error::AnonymousStamp);
+        }
+        let mut left = Vec::with_capacity(self.i.nodes.len() + 2);
+        let mut right = Vec::with_capacity(self.i.nodes.len() + 2);
+        split_into(&self.i.nodes, 0, &mut left, &mut right);
+        let s1 = self.with_parts(FlatIdTree { nodes: left }, self.e.clone());
+        let s2 = self.with_parts(FlatIdTree { nodes: right }, self.e.clone());
+        (s1, s2)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the ids overlap, the stamps are from different epochs or a
+    /// counter overflows. Use `try_join` for stamps that may not be
+    /// disjoint.
+    fn join(&self, other: &FlatStamp<N>) -> FlatStamp<N> {
+        if self.epoch != other.epoch {
+            panic!("{}", Error::EpochMismatch);
+        }
+        let sum_i = match self.i.try_sum(&other.i) {
+            Ok(i) => i,
+            Err(e) => panic!("{}", e),
+        };
+        let join_e = self.e.join(&other.e);
+        self.with_parts(sum_i, join_e)
+    }
+
+    /// Panics if the stamp is anonymous or the counter overflows; see `try_event`.
+    fn event(&self) -> FlatStamp<N> {
+        match self.event_with(OverflowPolicy::Error) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn send(&self) -> (FlatStamp<N>, FlatStamp<N>) {
+        self.event().peek()
+    }
+
+    fn receive(&self, other: &FlatStamp<N>) -> FlatStamp<N> {
+        self.join(other).event()
+    }
+
+    fn sync(&self, other: &FlatStamp<N>) -> (FlatStamp<N>, FlatStamp<N>) {
+        self.join(other).fork()
+    }
+}
+
+impl From<&IdTree> for FlatIdTree {
+    fn from(i: &IdTree) -> FlatIdTree {
+        fn push(i: &IdTree, nodes: &mut Vec<IdNode>) {
+            match *i {
+                IdTree::Leaf { i } => nodes.push(IdNode::Leaf(i)),
+                IdTree::Node {
+                    ref left,
+                    ref right,
+                } => {
+                    nodes.push(IdNode::Node);
+                    push(left, nodes);
+                    push(right, nodes);
+                }
+            }
+        }
+
+        let mut nodes = Vec::new();
+        push(i, &mut nodes);
+        FlatIdTree { nodes }
+    }
+}
+
+impl From<&FlatIdTree> for IdTree {
+    fn from(i: &FlatIdTree) -> IdTree {
+        fn build(nodes: &[IdNode], p: &mut usize) -> IdTree {
+            let node = nodes[*p];
+            *p += 1;
+            match node {
+                IdNode::Leaf(i) => IdTree::leaf(i),
+                IdNode::Node => {
+                    let left = build(nodes, p);
+                    let right = build(nodes, p);
+                    IdTree::node(Box::new(left), Box::new(right))
+                }
+            }
+        }
+
+        build(&i.nodes, &mut 0)
+    }
+}
+
+impl<N: Counter> From<&EventTree<N>> for FlatEventTree<N> {
+    fn from(e: &EventTree<N>) -> FlatEventTree<N> {
+        fn push<N: Counter>(e: &EventTree<N>, nodes: &mut Vec<EventNode<N>>) {
+            match *e {
+                EventTree::Leaf { n } => nodes.push(EventNode::Leaf(n)),
+                EventTree::Node {
+                    n,
+                    ref left,
+                    ref right,
+                } => {
+                    nodes.push(EventNode::Node(n));
+                    push(left, nodes);
+                    push(right, nodes);
+                }
+            }
+        }
+
+        let mut nodes = Vec::new();
+        push(e, &mut nodes);
+        FlatEventTree { nodes }
+    }
+}
+
+impl<N: Counter> From<&FlatEventTree<N>> for EventTree<N> {
+    fn from(e: &FlatEventTree<N>) -> EventTree<N> {
+        fn build<N: Counter>(nodes: &[EventNode<N>], p: &mut usize) -> EventTree<N> {
+            let node = nodes[*p];
+            *p += 1;
+            match node {
+                EventNode::Leaf(n) => EventTree::new_leaf(n),
+                EventNode::Node(n) => {
+                    let left = build(nodes, p);
+                    let right = build(nodes, p);
+                    EventTree::new_node(n, Box::new(left), Box::new(right))
+                }
+            }
+        }
+
+        build(&e.nodes, &mut 0)
+    }
+}
+
+impl<N: Counter> From<&Stamp<N>> for FlatStamp<N> {
+    fn from(s: &Stamp<N>) -> FlatStamp<N> {
+        FlatStamp {
+            i: FlatIdTree::from(&s.i),
+            e: FlatEventTree::from(&s.e),
+            epoch: s.epoch,
+        }
+    }
+}
+
+impl<N: Counter> From<&FlatStamp<N>> for Stamp<N> {
+    fn from(s: &FlatStamp<N>) -> Stamp<N> {
+        Stamp {
+            i: IdTree::from(&s.i),
+            e: EventTree::from(&s.e),
+            epoch: s.epoch,
+        }
+    }
+}
+
+// an event subtree: the nodes and the position of its root
+type Ev<'a, N> = (&'a [EventNode<N>], usize);
+
+// an event subtree's root counter and, for a node, its children
+type EvParts<'a, N> = (N, Option<(Ev<'a, N>, Ev<'a, N>)>);
+
+// returns the position just past the subtree rooted at p
+fn id_end(ids: &[IdNode], mut p: usize) -> usize {
+    let mut pending = 1;
+    while pending > 0 {
+        match ids[p] {
+            IdNode::Leaf(_) => pending -= 1,
+            IdNode::Node => pending += 1,
+        }
+        p += 1;
+    }
+    p
+}
+
+fn event_end<N>(ev: &[EventNode<N>], mut p: usize) -> usize {
+    let mut pending = 1;
+    while pending > 0 {
+        match ev[p] {
+            EventNode::Leaf(_) => pending -= 1,
+            EventNode::Node(_) => pending += 1,
+        }
+        p += 1;
+    }
+    p
+}
+
+fn id_children(ids: &[IdNode], p: usize) -> (usize, usize) {
+    match ids[p] {
+        IdNode::Node => (p + 1, id_end(ids, p + 1)),
+        // corrupted tree?
+        IdNode::Leaf(_) => unreachable!(),
+    }
+}
+
+fn event_children<N: Counter>((ev, p): Ev<N>) -> EvParts<N> {
+    match ev[p] {
+        EventNode::Leaf(n) => (n, None),
+        EventNode::Node(n) => (n, Some(((ev, p + 1), (ev, event_end(ev, p + 1))))),
+    }
+}
+
+fn copy_event<N: Counter>((ev, p): Ev<N>, out: &mut Vec<EventNode<N>>) {
+    out.extend_from_slice(&ev[p..event_end(ev, p)]);
+}
+
+fn event_min<N: Counter>(ev: Ev<N>, policy: OverflowPolicy) -> Result<N, Error> {
+    match event_children(ev) {
+        (n, None) => Ok(n),
+        (n, Some((left, right))) => {
+            let m = cmp::min(event_min(left, policy)?, event_min(right, policy)?);
+            policy.add(n, m)
+        }
+    }
+}
+
+fn event_max<N: Counter>(ev: Ev<N>, policy: OverflowPolicy) -> Result<N, Error> {
+    match event_children(ev) {
+        (n, None) => Ok(n),
+        (n, Some((left, right))) => {
+            let m = cmp::max(event_max(left, policy)?, event_max(right, policy)?);
+            policy.add(n, m)
+        }
+    }
+}
+
+// collapses the node at k if its children are equal leaves
+fn norm_id_at(out: &mut Vec<IdNode>, k: usize) {
+    if out.len() == k + 3 {
+        if let (IdNode::Leaf(i1), IdNode::Leaf(i2)) = (out[k + 1], out[k + 2]) {
+            if i1 == i2 {
+                out.truncate(k);
+                out.push(IdNode::Leaf(i1));
+            }
+        }
+    }
+}
+
+// normalises the node at k, assuming both of its subtrees are normalised
+fn norm_event_at<N: Counter>(
+    out: &mut Vec<EventNode<N>>,
+    k: usize,
+    policy: OverflowPolicy,
+) -> Result<(), Error> {
+    let n = out[k].n();
+    let l = k + 1;
+    let r = event_end(out, l);
+
+    if let (EventNode::Leaf(m1), EventNode::Leaf(m2)) = (out[l], out[r]) {
+        if m1 == m2 {
+            out.truncate(k);
+            out.push(EventNode::Leaf(policy.add(n, m1)?));
+            return Ok(());
+        }
+    }
+
+    let m = cmp::min(out[l].n(), out[r].n());
+    out[k] = EventNode::Node(policy.add(n, m)?);
+    out[l] = out[l].with_n(out[l].n() - m);
+    out[r] = out[r].with_n(out[r].n() - m);
+    Ok(())
+}
+
+fn split_into(ids: &[IdNode], p: usize, a: &mut Vec<IdNode>, b: &mut Vec<IdNode>) {
+    match ids[p] {
+        IdNode::Leaf(false) => {
+            a.push(IdNode::Leaf(false));
+            b.push(IdNode::Leaf(false));
+        }
+        IdNode::Leaf(true) => {
+            a.extend_from_slice(&[IdNode::Node, IdNode::Leaf(true), IdNode::Leaf(false)]);
+            b.extend_from_slice(&[IdNode::Node, IdNode::Leaf(false), IdNode::Leaf(true)]);
+        }
+        IdNode::Node => {
+            let (l, r) = id_children(ids, p);
+            if ids[l] == IdNode::Leaf(false) {
+                a.extend_from_slice(&[IdNode::Node, IdNode::Leaf(false)]);
+                b.extend_from_slice(&[IdNode::Node, IdNode::Leaf(false)]);
+                split_into(ids, r, a, b);
+            } else if ids[r] == IdNode::Leaf(false) {
+                a.push(IdNode::Node);
+                b.push(IdNode::Node);
+                split_into(ids, l, a, b);
+                a.push(IdNode::Leaf(false));
+                b.push(IdNode::Leaf(false));
+            } else {
+                a.push(IdNode::Node);
+                a.extend_from_slice(&ids[l..r]);
+                a.push(IdNode::Leaf(false));
+                b.extend_from_slice(&[IdNode::Node, IdNode::Leaf(false)]);
+                b.extend_from_slice(&ids[r..id_end(ids, r)]);
+            }
+        }
+    }
+}
+
+fn try_sum_into(
+    x: &[IdNode],
+    px: usize,
+    y: &[IdNode],
+    py: usize,
+    out: &mut Vec<IdNode>,
+) -> Result<(), Error> {
+    match (x[px], y[py]) {
+        (IdNode::Leaf(false), _) => {
+            out.extend_from_slice(&y[py..id_end(y, py)]);
+            Ok(())
+        }
+        (_, IdNode::Leaf(false)) => {
+            out.extend_from_slice(&x[px..id_end(x, px)]);
+            Ok(())
+        }
+        (IdNode::Node, IdNode::Node) => {
+            let k = out.len();
+            out.push(IdNode::Node);
+            try_sum_into(x, px + 1, y, py + 1, out)?;
+            try_sum_into(x, id_end(x, px + 1), y, id_end(y, py + 1), out)?;
+            norm_id_at(out, k);
+            Ok(())
+        }
+        _ => Err(Error::IdOverlap),
+    }
+}
+
+// joins a lifted by da with b lifted by db
+fn join_into<N: Counter>(
+    a: Ev<N>,
+    da: N,
+    b: Ev<N>,
+    db: N,
+    policy: OverflowPolicy,
+    out: &mut Vec<EventNode<N>>,
+) -> Result<(), Error> {
+    let (na, children_a) = event_children(a);
+    let (nb, children_b) = event_children(b);
+    let va = policy.add(na, da)?;
+    let vb = policy.add(nb, db)?;
+
+    if children_a.is_none() && children_b.is_none() {
+        out.push(EventNode::Leaf(cmp::max(va, vb)));
+        return Ok(());
+    }
+
+    let zero = [EventNode::Leaf(N::ZERO)];
+    let z = (&zero[..], 0);
+    let (left_a, right_a) = children_a.unwrap_or((z, z));
+    let (left_b, right_b) = children_b.unwrap_or((z, z));
+    let n = cmp::min(va, vb);
+
+    let k = out.len();
+    out.push(EventNode::Node(n));
+    join_into(left_a, va - n, left_b, vb - n, policy, out)?;
+    join_into(right_a, va - n, right_b, vb - n, policy, out)?;
+    norm_event_at(out, k, policy)
+}
+
+// returns (a <= b, b <= a) where each tree is lifted by its base
+fn compare<N: Counter>(a: Ev<N>, base_a: u128, b: Ev<N>, base_b: u128) -> (bool, bool) {
+    let (na, children_a) = event_children(a);
+    let (nb, children_b) = event_children(b);
+    let va = lift_wide(base_a, na);
+    let vb = lift_wide(base_b, nb);
+    let mut leq = va <= vb;
+    let mut geq = vb <= va;
+
+    if children_a.is_none() && children_b.is_none() {
+        return (leq, geq);
+    }
+
+    let zero = [EventNode::Leaf(N::ZERO)];
+    let z = (&zero[..], 0);
+    let (left_a, right_a) = children_a.unwrap_or((z, z));
+    let (left_b, right_b) = children_b.unwrap_or((z, z));
+
+    for (ca, cb) in [(left_a, left_b), (right_a, right_b)] {
+        if !leq && !geq {
+            break;
+        }
+        let (l, g) = compare(ca, va, cb, vb);
+        leq &= l;
+        geq &= g;
+    }
+
+    (leq, geq)
+}
+
+fn fill_into<N: Counter>(
+    ids: &[IdNode],
+    pi: usize,
+    ev: Ev<N>,
+    policy: OverflowPolicy,
+    out: &mut Vec<EventNode<N>>,
+) -> Result<(), Error> {
+    match ids[pi] {
+        IdNode::Leaf(false) => copy_event(ev, out),
+        IdNode::Leaf(true) => out.push(EventNode::Leaf(event_max(ev, policy)?)),
+        IdNode::Node => match event_children(ev) {
+            (_, None) => copy_event(ev, out),
+            (n, Some((e_left, e_right))) => {
+                let (i_left, i_right) = id_children(ids, pi);
+                let k = out.len();
+                out.push(EventNode::Node(n));
+
+                if ids[i_left] == IdNode::Leaf(true) {
+                    // placeholder for the filled left leaf, which depends on the right
+                    out.push(EventNode::Leaf(N::ZERO));
+                    let r = out.len();
+                    fill_into(ids, i_right, e_right, policy, out)?;
+                    let m = cmp::max(event_max(e_left, policy)?, event_min((out, r), policy)?);
+                    out[k + 1] = EventNode::Leaf(m);
+                } else if ids[i_right] == IdNode::Leaf(true) {
+                    let l = out.len();
+                    fill_into(ids, i_left, e_left, policy, out)?;
+                    let m = cmp::max(event_max(e_right, policy)?, event_min((out, l), policy)?);
+                    out.push(EventNode::Leaf(m));
+                } else {
+                    fill_into(ids, i_left, e_left, policy, out)?;
+                    fill_into(ids, i_right, e_right, policy, out)?;
+                }
+
+                norm_event_at(out, k, policy)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+// summarises growing each subtree in one bottom-up pass, and records at
+// each id node whether strategy grows its left side
+fn grow_plan<N: Counter>(
+    ids: &[IdNode],
+    pi: usize,
+    ev: Ev<N>,
+    strategy: &dyn GrowStrategy,
+    grow_left: &mut [bool],
+) -> Result<GrowSide, Error> {
+    let (_, children) = event_children(ev);
+    match (ids[pi], children) {
+        (IdNode::Leaf(false), _) => return Ok(GrowSide::unowned(event_depth(ev))),
+        (IdNode::Leaf(true), None) => return Ok(GrowSide::owned_leaf()),
+        // fill collapses an event node under an owned leaf first
+        (IdNode::Leaf(true), Some(_)) => return Err(Error::NonNormalEventTree),
+        (IdNode::Node, _) => {}
+    }
+
+    let zero = [EventNode::Leaf(N::ZERO)];
+    let z = (&zero[..], 0);
+    let (e_left, e_right) = children.unwrap_or((z, z));
+    let (i_left, i_right) = id_children(ids, pi);
+
+    let left = grow_plan(ids, i_left, e_left, strategy, grow_left)?;
+    let right = grow_plan(ids, i_right, e_right, strategy, grow_left)?;
+    // growing below a leaf first splits it into a node
+    let (side, left) = GrowSide::node(strategy, left, right, children.is_none())?;
+    grow_left[pi] = left;
+    Ok(side)
+}
+
+fn event_depth<N>((ev, p): Ev<N>) -> usize {
+    // the children still to visit of each node above the current one
+    let mut pending: Vec<u8> = Vec::new();
+    let mut deepest = 0;
+    for node in &ev[p..event_end(ev, p)] {
+        deepest = cmp::max(deepest, pending.len());
+        match node {
+            EventNode::Node(_) => pending.push(2),
+            EventNode::Leaf(_) => {
+                while let Some(children) = pending.last_mut() {
+                    *children -= 1;
+                    if *children > 0 {
+                        break;
+                    }
+                    pending.pop();
+                }
+            }
+        }
+    }
+    deepest
+}
+
+// grows the event tree, with counters below it lifted by base, along the
+// sides grow_plan chose. false if the grown counter was saturated instead.
+fn grow_into<N: Counter>(
+    ids: &[IdNode],
+    pi: usize,
+    ev: Ev<N>,
+    base: N,
+    grow_left: &[bool],
+    policy: OverflowPolicy,
+    out: &mut Vec<EventNode<N>>,
+) -> Result<bool, Error> {
+    let (n, children) = event_children(ev);
+    if children.is_none() && ids[pi] == IdNode::Leaf(true) {
+        let value = policy.add(base, n)?;
+        let step = policy.add(value, N::ONE)? - value;
+        out.push(EventNode::Leaf(n + step));
+        return Ok(step != N::ZERO);
+    }
+
+    let zero = [EventNode::Leaf(N::ZERO)];
+    let z = (&zero[..], 0);
+    let (e_left, e_right) = children.unwrap_or((z, z));
+    let (i_left, i_right) = id_children(ids, pi);
+    let base = policy.add(base, n)?;
+
+    out.push(EventNode::Node(n));
+    if grow_left[pi] {
+        let grown = grow_into(ids, i_left, e_left, base, grow_left, policy, out)?;
+        copy_event(e_right, out);
+        Ok(grown)
+    } else {
+        copy_event(e_left, out);
+        grow_into(ids, i_right, e_right, base, grow_left, policy, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    impl Representation for FlatStamp {
+        fn seed() -> FlatStamp {
+            FlatStamp::seed()
+        }
+
+        fn causality(&self, other: &FlatStamp) -> Causality {
+            FlatStamp::causality(self, other)
+        }
+
+        fn epoch(&self) -> u32 {
+            FlatStamp::epoch(self)
+        }
+
+        fn validate(&self) -> Result<(), Error> {
+            FlatStamp::validate(self)
+        }
+
+        fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+            FlatStamp::validate_within(self, max_depth)
+        }
+
+        fn try_join(&self, other: &FlatStamp) -> Result<FlatStamp, Error> {
+            FlatStamp::try_join(self, other)
+        }
+
+        fn try_event(&self) -> Result<FlatStamp, Error> {
+            FlatStamp::try_event(self)
+        }
+
+        fn event_with(&self, policy: OverflowPolicy) -> Result<FlatStamp, Error> {
+            FlatStamp::event_with(self, policy)
+        }
+    }
+
+    #[test]
+    fn conversion_round_trip() {
+        check_round_trip::<FlatStamp>();
+    }
+
+    #[test]
+    fn keeps_epoch() {
+        check_keeps_epoch::<FlatStamp>();
+    }
+
+    #[test]
+    #[should_panic(expected = "stamps are from different epochs")]
+    fn join_across_epochs_panics() {
+        join_across_epochs::<FlatStamp>();
+    }
+
+    #[test]
+    fn matches_boxed_example() {
+        let s = Stamp::seed();
+        let f = FlatStamp::seed();
+        check_converts(&s, &f);
+
+        let (sl, sr) = s.fork();
+        let (fl, fr) = f.fork();
+        check_converts(&sl, &fl);
+        check_converts(&sr, &fr);
+
+        let (sle, sre) = (sl.event(), sr.event());
+        let (fle, fre) = (fl.event(), fr.event());
+        check_converts(&sle, &fle);
+        check_converts(&sre, &fre);
+
+        let (slel, sler) = sle.fork();
+        let (flel, fler) = fle.fork();
+        check_converts(&slel, &flel);
+        check_converts(&sler, &fler);
+
+        let sree = sre.event();
+        let free = fre.event();
+        check_converts(&sree, &free);
+
+        let j = sler.join(&sree);
+        let fj = fler.join(&free);
+        check_converts(&j, &fj);
+        check_converts(
+            &slel.event().join(&j.fork().0),
+            &flel.event().join(&fj.fork().0),
+        );
+    }
+
+    #[test]
+    fn matches_boxed_random() {
+        check_matches_boxed_random::<FlatStamp>(7);
+    }
+
+    #[test]
+    fn fallible_verbs_match_boxed() {
+        check_fallible_verbs::<FlatStamp>();
+    }
+
+    #[test]
+    fn validate_matches_boxed() {
+        check_validate::<FlatStamp>();
+    }
+
+    #[test]
+    fn overlapping_ids() {
+        let (a, _) = FlatStamp::seed().fork();
+        assert_eq!(Err(Error::IdOverlap), a.i.try_sum(&a.i));
+    }
+
+    #[test]
+    fn compare_near_counter_max() {
+        check_compare_near_counter_max::<FlatStamp>();
+    }
+}
@@ -25,20 +25,58 @@
 //! the 3 derived verbs: send, receive and sync,
 //! and a partial ordering to establish causality / the happens-before relation.
 //! Also in the box is a simple ascii coding representation suitable
-//! for printing to stdout and use in protocols.
+//! for printing to stdout and use in protocols, and the compact binary
+//! coding from the paper for piggybacking stamps on network messages.
+//! Both can carry a `delta::StampDelta` against a stamp the peer already
+//! holds, see `Stamp::delta_from`.
+//! `flat::FlatStamp` stores each tree in a single preorder `Vec` for
+//! callers that want fewer allocations per clock operation, and with the
+//! `shared` feature `shared::SharedStamp` shares subtrees between stamps.
+//! `IdTree::intervals` describes an id as exact `dyadic::Dyadic` ranges,
+//! and `EventTree::segments` an event tree as a step function over them.
+//! `event` grows the event tree as the paper does, picking the subtree
+//! with the smallest `cost::Cost`; `event_with_strategy` on each stamp
+//! type, and `Stamp::event_mut_with_strategy`, take any other
+//! `cost::GrowStrategy`.
+//!
+//! Event counters are `u32` by default. Long-lived replicas can use any
+//! other `counter::Counter`, e.g. `Stamp::<u64>::new_seed()`.
 
 use std::borrow::Cow;
 use std::cmp;
+use std::mem;
 
-//pub mod ascii_coding;
+pub mod ascii_coding;
+pub mod binary_coding;
 pub mod cost;
+pub mod counter;
+pub mod delta;
+pub mod dyadic;
+pub mod error;
+pub mod flat;
+pub mod overflow;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "shared")]
+pub mod shared;
 
 use cost::*;
+use counter::Counter;
+use error::Error;
+use overflow::OverflowPolicy;
+
+/// The deepest tree accepted from untrusted input by default.
+///
+/// The tree algorithms recurse once per level, so the decoders and
+/// `validate` reject anything deeper to keep peer-supplied stamps from
+/// overflowing the stack. At this depth every clock verb stays well within
+/// a 2 MB thread stack even in debug builds, and every tree has exact
+/// `dyadic` views. Callers with larger stacks can raise the limit with
+/// `validate_within` and the decoders' `with_max_depth`.
+///
+/// Each fork deepens an id by at most one level.
+pub const MAX_DEPTH: usize = 127;
 
-#[cfg(feature = "serde")]
-use serde::{Serialize, Deserialize};
-
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq)]
 pub enum IdTree {
     Leaf {
@@ -50,28 +88,29 @@ pub enum IdTree {
     },
 }
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, PartialEq )]
-pub enum EventTree {
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTree<N = u32> {
     Leaf {
-        n: u32,
+        n: N,
     },
     Node {
-        n: u32,
-        left: Box<EventTree>,
-        right: Box<EventTree>,
+        n: N,
+        left: Box<EventTree<N>>,
+        right: Box<EventTree<N>>,
     },
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Stamp {
+pub struct Stamp<N = u32> {
     i: IdTree,
-    e: EventTree,
+    e: EventTree<N>,
+    // bumped by each rebase; stamps of different epochs are incomparable
+    epoch: u32,
 }
 
 impl IdTree {
     pub fn leaf(i: bool) -> IdTree {
-        IdTree::Leaf { i: i }
+        IdTree::Leaf { i }
     }
 
     pub fn zero() -> IdTree {
@@ -83,233 +122,701 @@ impl IdTree {
     }
 
     pub fn node(left: Box<IdTree>, right: Box<IdTree>) -> IdTree {
-        IdTree::Node {
-            left: left,
-            right: right,
+        IdTree::Node { left, right }
+    }
+
+    /// Checks that the tree is in normal form and no deeper than `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        let mut stack = vec![(self, 0)];
+        while let Some((tree, depth)) = stack.pop() {
+            if depth > max_depth {
+                return Err(Error::DepthLimitExceeded);
+            }
+            if let IdTree::Node {
+                ref left,
+                ref right,
+            } = *tree
+            {
+                if let (IdTree::Leaf { i: i1 }, IdTree::Leaf { i: i2 }) = (&**left, &**right) {
+                    if i1 == i2 {
+                        return Err(Error::NonNormalIdTree);
+                    }
+                }
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of levels below the root, computed without recursion.
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 0)];
+        while let Some((tree, depth)) = stack.pop() {
+            deepest = cmp::max(deepest, depth);
+            if let IdTree::Node {
+                ref left,
+                ref right,
+            } = *tree
+            {
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
+        }
+        deepest
+    }
+
+    /// Sums two ids, failing instead of panicking if they overlap.
+    pub fn try_sum(&self, other: &IdTree) -> Result<IdTree, Error> {
+        match (self, other) {
+            (IdTree::Leaf { i: false }, _) => Ok(other.clone()),
+            (_, IdTree::Leaf { i: false }) => Ok(self.clone()),
+            (
+                IdTree::Node {
+                    left: left1,
+                    right: right1,
+                },
+                IdTree::Node {
+                    left: left2,
+                    right: right2,
+                },
+            ) => {
+                let new_left = Box::new(left1.try_sum(left2)?);
+                let new_right = Box::new(right1.try_sum(right2)?);
+                Ok(IdTree::node(new_left, new_right).norm())
+            }
+            _ => Err(Error::IdOverlap),
+        }
+    }
+
+    /// Whether both ids own some part of the same sub-interval, in which
+    /// case they cannot be summed. Expects normalised ids.
+    pub fn overlaps(&self, other: &IdTree) -> bool {
+        match (self, other) {
+            (IdTree::Leaf { i: false }, _) | (_, IdTree::Leaf { i: false }) => false,
+            (IdTree::Leaf { i: true }, _) | (_, IdTree::Leaf { i: true }) => true,
+            (
+                IdTree::Node {
+                    left: left1,
+                    right: right1,
+                },
+                IdTree::Node {
+                    left: left2,
+                    right: right2,
+                },
+            ) => left1.overlaps(left2) || right1.overlaps(right2),
+        }
+    }
+
+    /// The sub-intervals owned by both ids.
+    pub fn intersect(&self, other: &IdTree) -> IdTree {
+        self.combine(other, &|a, b| a && b)
+    }
+
+    /// The sub-intervals owned by this id but not `other`.
+    pub fn difference(&self, other: &IdTree) -> IdTree {
+        self.combine(other, &|a, b| a && !b)
+    }
+
+    /// The sub-intervals this id does not own.
+    pub fn complement(&self) -> IdTree {
+        match *self {
+            IdTree::Leaf { i } => IdTree::leaf(!i),
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => {
+                let mut i = IdTree::node(Box::new(left.complement()), Box::new(right.complement()));
+                i.norm_node_in_place();
+                i
+            }
+        }
+    }
+
+    /// Whether every sub-interval this id owns is also owned by `other`.
+    pub fn is_subset_of(&self, other: &IdTree) -> bool {
+        match (self, other) {
+            (IdTree::Leaf { i: a }, IdTree::Leaf { i: b }) => !a || *b,
+            _ => {
+                let (left1, right1) = self.halves();
+                let (left2, right2) = other.halves();
+                left1.is_subset_of(left2) && right1.is_subset_of(right2)
+            }
+        }
+    }
+
+    /// Whether the id owns nothing, like an anonymous stamp's.
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            IdTree::Leaf { i } => !i,
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => left.is_empty() && right.is_empty(),
+        }
+    }
+
+    /// Whether the id owns the whole interval, like a seed stamp's.
+    pub fn is_full(&self) -> bool {
+        match *self {
+            IdTree::Leaf { i } => i,
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => left.is_full() && right.is_full(),
+        }
+    }
+
+    // applies op leaf by leaf, normalising as it goes
+    fn combine(&self, other: &IdTree, op: &dyn Fn(bool, bool) -> bool) -> IdTree {
+        match (self, other) {
+            (IdTree::Leaf { i: a }, IdTree::Leaf { i: b }) => IdTree::leaf(op(*a, *b)),
+            _ => {
+                let (left1, right1) = self.halves();
+                let (left2, right2) = other.halves();
+                let mut i = IdTree::node(
+                    Box::new(left1.combine(left2, op)),
+                    Box::new(right1.combine(right2, op)),
+                );
+                i.norm_node_in_place();
+                i
+            }
+        }
+    }
+
+    // a node's children, or a leaf standing for both of its halves
+    fn halves(&self) -> (&IdTree, &IdTree) {
+        match *self {
+            IdTree::Leaf { .. } => (self, self),
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => (left, right),
         }
     }
 }
 
+// The constructors below fix the counter to the default `u32` so that
+// `EventTree::zero()` and `EventTree::leaf(1)` need no type annotations.
+// Use the `new_` variants to build trees with other counter types.
 impl EventTree {
     pub fn zero() -> EventTree {
-        EventTree::leaf(0)
+        EventTree::new_zero()
     }
 
     pub fn leaf(n: u32) -> EventTree {
-        EventTree::Leaf { n: n }
+        EventTree::new_leaf(n)
     }
 
     pub fn node(n: u32, left: Box<EventTree>, right: Box<EventTree>) -> EventTree {
-        EventTree::Node {
-            n: n,
-            left: left,
-            right: right,
+        EventTree::new_node(n, left, right)
+    }
+}
+
+impl<N: Counter> EventTree<N> {
+    pub fn new_zero() -> EventTree<N> {
+        EventTree::new_leaf(N::ZERO)
+    }
+
+    pub fn new_leaf(n: N) -> EventTree<N> {
+        EventTree::Leaf { n }
+    }
+
+    pub fn new_node(n: N, left: Box<EventTree<N>>, right: Box<EventTree<N>>) -> EventTree<N> {
+        EventTree::Node { n, left, right }
+    }
+
+    pub fn n(&self) -> N {
+        match *self {
+            EventTree::Leaf { n } => n,
+            EventTree::Node { n, .. } => n,
         }
     }
 
-    pub fn n(&self) -> u32 {
+    /// Panics if a counter overflows.
+    pub fn lift(self, m: N) -> EventTree<N> {
+        self.lift_with(m, OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+
+    /// Like `lift`, but applies `policy` if the counter overflows.
+    pub fn lift_with(self, m: N, policy: OverflowPolicy) -> Result<EventTree<N>, Error> {
         match self {
-            &EventTree::Leaf { n } => n,
-            &EventTree::Node { n, .. } => n,
+            EventTree::Leaf { n } => Ok(EventTree::new_leaf(policy.add(n, m)?)),
+            EventTree::Node { n, left, right } => {
+                Ok(EventTree::new_node(policy.add(n, m)?, left, right))
+            }
         }
     }
 
-    pub fn lift(self, m: u32) -> EventTree {
+    /// Panics if the counter underflows.
+    pub fn sink(self, m: N) -> EventTree<N> {
+        self.try_sink(m).expect("event counter underflow")
+    }
+
+    /// Like `sink`, but fails if the counter underflows.
+    ///
+    /// Sinking below zero only happens on malformed trees, so underflow is
+    /// always an error rather than subject to an `OverflowPolicy`.
+    pub fn try_sink(self, m: N) -> Result<EventTree<N>, Error> {
         match self {
-            EventTree::Leaf { n } => EventTree::leaf(n + m),
-            EventTree::Node { n, left, right } => EventTree::node(n + m, left, right),
+            EventTree::Leaf { n } => Ok(EventTree::new_leaf(
+                n.checked_sub(m).ok_or(Error::CounterUnderflow)?,
+            )),
+            EventTree::Node { n, left, right } => Ok(EventTree::new_node(
+                n.checked_sub(m).ok_or(Error::CounterUnderflow)?,
+                left,
+                right,
+            )),
         }
     }
 
-    pub fn sink(self, m: u32) -> EventTree {
-        match self {
-            EventTree::Leaf { n } => EventTree::leaf(n - m),
-            EventTree::Node { n, left, right } => EventTree::node(n - m, left, right),
+    /// Panics if a counter overflows.
+    pub fn join(&self, other: &EventTree<N>) -> EventTree<N> {
+        self.join_with(other, OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+
+    /// Like `join`, but applies `policy` if a counter overflows.
+    ///
+    /// Only the output tree is allocated; lifts are passed down as offsets.
+    pub fn join_with(
+        &self,
+        other: &EventTree<N>,
+        policy: OverflowPolicy,
+    ) -> Result<EventTree<N>, Error> {
+        EventTree::combine_parts(
+            self.parts(),
+            N::ZERO,
+            other.parts(),
+            N::ZERO,
+            cmp::max,
+            policy,
+        )
+    }
+
+    /// The pointwise minimum of the two trees: the history both have seen.
+    ///
+    /// Panics if a counter overflows.
+    pub fn meet(&self, other: &EventTree<N>) -> EventTree<N> {
+        self.meet_with(other, OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+
+    /// Like `meet`, but applies `policy` if a counter overflows.
+    pub fn meet_with(
+        &self,
+        other: &EventTree<N>,
+        policy: OverflowPolicy,
+    ) -> Result<EventTree<N>, Error> {
+        EventTree::combine_parts(
+            self.parts(),
+            N::ZERO,
+            other.parts(),
+            N::ZERO,
+            cmp::min,
+            policy,
+        )
+    }
+
+    /// Subtracts a stable cut that this tree has seen, to keep its counters
+    /// small; see `Stamp::rebase`.
+    ///
+    /// Panics if `stable` is not `leq` this tree.
+    pub fn rebase(&self, stable: &EventTree<N>) -> EventTree<N> {
+        match self.try_rebase(stable) {
+            Ok(e) => e,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `rebase`, but fails with `CounterUnderflow` if `stable` is not
+    /// `leq` this tree.
+    pub fn try_rebase(&self, stable: &EventTree<N>) -> Result<EventTree<N>, Error> {
+        EventTree::rebase_parts(self.parts(), N::ZERO, stable.parts(), N::ZERO)
+    }
+
+    // subtracts the second tree lifted by lift2 from the first lifted by
+    // lift1. unlike in combine_parts, a base common to both cancels out.
+    fn rebase_parts(
+        (n1, children1): Parts<N>,
+        lift1: N,
+        (n2, children2): Parts<N>,
+        lift2: N,
+    ) -> Result<EventTree<N>, Error> {
+        let policy = OverflowPolicy::Error;
+        let m1 = policy.add(n1, lift1)?;
+        let m2 = policy.add(n2, lift2)?;
+
+        if children1.is_none() && children2.is_none() {
+            return m1
+                .checked_sub(m2)
+                .map(EventTree::new_leaf)
+                .ok_or(Error::CounterUnderflow);
+        }
+
+        let zero = (N::ZERO, None);
+        let (left1, right1) = children1.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+        let (left2, right2) = children2.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+        let n = cmp::min(m1, m2);
+
+        let new_left = EventTree::rebase_parts(left1, m1 - n, left2, m2 - n)?;
+        let new_right = EventTree::rebase_parts(right1, m1 - n, right2, m2 - n)?;
+        EventTree::norm_node(N::ZERO, new_left, new_right, policy)
+    }
+
+    // combines the first tree lifted by lift1 with the second lifted by lift2,
+    // taking op (max for join, min for meet) of the two pointwise.
+    // a leaf is treated as a node with zero children when combined with a node.
+    fn combine_parts(
+        (n1, children1): Parts<N>,
+        lift1: N,
+        (n2, children2): Parts<N>,
+        lift2: N,
+        op: fn(N, N) -> N,
+        policy: OverflowPolicy,
+    ) -> Result<EventTree<N>, Error> {
+        let m1 = policy.add(n1, lift1)?;
+        let m2 = policy.add(n2, lift2)?;
+
+        if children1.is_none() && children2.is_none() {
+            return Ok(EventTree::new_leaf(op(m1, m2)));
         }
+
+        let zero = (N::ZERO, None);
+        let (left1, right1) = children1.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+        let (left2, right2) = children2.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+        let n = cmp::min(m1, m2);
+
+        let new_left = EventTree::combine_parts(left1, m1 - n, left2, m2 - n, op, policy)?;
+        let new_right = EventTree::combine_parts(right1, m1 - n, right2, m2 - n, op, policy)?;
+        EventTree::norm_node(n, new_left, new_right, policy)
     }
 
-    pub fn join(&self, other: &EventTree) -> EventTree {
+    /// Like `min`, but applies `policy` if the result overflows.
+    pub fn min_with(&self, policy: OverflowPolicy) -> Result<N, Error> {
         match *self {
-            EventTree::Leaf { n: n1 } => match *other {
-                EventTree::Leaf { n: n2 } => EventTree::leaf(cmp::max(n1, n2)),
-                EventTree::Node { .. } => {
-                    let new_left = EventTree::node(
-                        n1,
-                        Box::new(EventTree::zero()),
-                        Box::new(EventTree::zero()),
-                    );
-                    new_left.join(other)
-                }
-            },
+            EventTree::Leaf { n } => Ok(n),
+            EventTree::Node {
+                n,
+                ref left,
+                ref right,
+            } => policy.add(n, cmp::min(left.min_with(policy)?, right.min_with(policy)?)),
+        }
+    }
+
+    /// Like `max`, but applies `policy` if the result overflows.
+    pub fn max_with(&self, policy: OverflowPolicy) -> Result<N, Error> {
+        match *self {
+            EventTree::Leaf { n } => Ok(n),
             EventTree::Node {
-                n: n1,
-                left: ref left1,
-                right: ref right1,
-            } => match *other {
-                EventTree::Leaf { n: n2 } => {
-                    let new_right = EventTree::node(
-                        n2,
-                        Box::new(EventTree::zero()),
-                        Box::new(EventTree::zero()),
-                    );
-                    self.join(&new_right)
+                n,
+                ref left,
+                ref right,
+            } => policy.add(n, cmp::max(left.max_with(policy)?, right.max_with(policy)?)),
+        }
+    }
+
+    /// Like `norm`, but applies `policy` if a counter overflows.
+    pub fn norm_with(self, policy: OverflowPolicy) -> Result<EventTree<N>, Error> {
+        match self {
+            EventTree::Leaf { n: _ } => Ok(self),
+            EventTree::Node { n, left, right } => {
+                let norm_left = left.norm_with(policy)?;
+                let norm_right = right.norm_with(policy)?;
+                EventTree::norm_node(n, norm_left, norm_right, policy)
+            }
+        }
+    }
+
+    // normalises a node whose children are already normalised
+    fn norm_node(
+        n: N,
+        norm_left: EventTree<N>,
+        norm_right: EventTree<N>,
+        policy: OverflowPolicy,
+    ) -> Result<EventTree<N>, Error> {
+        if let EventTree::Leaf { n: m1 } = norm_left {
+            if let EventTree::Leaf { n: m2 } = norm_right {
+                if m1 == m2 {
+                    return Ok(EventTree::new_leaf(policy.add(n, m1)?));
                 }
-                EventTree::Node {
-                    n: n2,
-                    left: ref left2,
-                    right: ref right2,
-                } => {
-                    if n1 > n2 {
-                        other.join(self)
-                    } else {
-                        let new_left = left1.join(&left2.clone().lift(n2 - n1));
-                        let new_right = right1.join(&right2.clone().lift(n2 - n1));
-                        EventTree::node(n1, Box::new(new_left), Box::new(new_right)).norm()
+            }
+        }
+
+        // normalised trees have min == n
+        let min_left = norm_left.n();
+        let min_right = norm_right.n();
+
+        let m = cmp::min(min_left, min_right);
+
+        Ok(EventTree::new_node(
+            policy.add(n, m)?,
+            Box::new(norm_left.try_sink(m)?),
+            Box::new(norm_right.try_sink(m)?),
+        ))
+    }
+
+    /// Checks that the tree is in normal form and no deeper than `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        let mut stack = vec![(self, 0)];
+        while let Some((tree, depth)) = stack.pop() {
+            if depth > max_depth {
+                return Err(Error::DepthLimitExceeded);
+            }
+            if let EventTree::Node {
+                ref left,
+                ref right,
+                ..
+            } = *tree
+            {
+                if let (EventTree::Leaf { n: n1 }, EventTree::Leaf { n: n2 }) = (&**left, &**right)
+                {
+                    if n1 == n2 {
+                        return Err(Error::NonNormalEventTree);
                     }
                 }
-            },
+                if cmp::min(left.n(), right.n()) != N::ZERO {
+                    return Err(Error::NonNormalEventTree);
+                }
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of levels below the root, computed without recursion.
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = vec![(self, 0)];
+        while let Some((tree, depth)) = stack.pop() {
+            deepest = cmp::max(deepest, depth);
+            if let EventTree::Node {
+                ref left,
+                ref right,
+                ..
+            } = *tree
+            {
+                stack.push((right, depth + 1));
+                stack.push((left, depth + 1));
+            }
         }
+        deepest
     }
 }
 
 impl Stamp {
     pub fn seed() -> Stamp {
-        Stamp::new(IdTree::one(), EventTree::zero())
+        Stamp::new_seed()
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    /// Like `seed`, for any counter type.
+    pub fn new_seed() -> Stamp<N> {
+        Stamp::new(IdTree::one(), EventTree::new_zero())
+    }
+
+    pub fn new(i: IdTree, e: EventTree<N>) -> Stamp<N> {
+        Stamp { i, e, epoch: 0 }
+    }
+
+    // a stamp in the same epoch as this one
+    fn with_parts(&self, i: IdTree, e: EventTree<N>) -> Stamp<N> {
+        Stamp {
+            i,
+            e,
+            epoch: self.epoch,
+        }
+    }
+
+    /// A stamp that carries `e` but owns no part of the interval, as
+    /// returned by `peek`. It can be joined but neither forks nor records
+    /// events.
+    pub fn anonymous(e: EventTree<N>) -> Stamp<N> {
+        Stamp::new(IdTree::zero(), e)
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.i == IdTree::zero()
+    }
+
+    /// Creates a stamp, checking that both trees are in normal form.
+    pub fn try_new(i: IdTree, e: EventTree<N>) -> Result<Stamp<N>, Error> {
+        let s = Stamp::new(i, e);
+        s.validate()?;
+        Ok(s)
+    }
+
+    /// Checks that both trees are in normal form and within `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        self.i.validate_within(max_depth)?;
+        self.e.validate_within(max_depth)
     }
 
-    pub fn new(i: IdTree, e: EventTree) -> Stamp {
-        Stamp { i: i, e: e }
+    /// Panics if a counter overflows.
+    pub fn fill(&self) -> Cow<'_, EventTree<N>> {
+        self.fill_with(OverflowPolicy::Error)
+            .expect("event counter overflow")
     }
 
-    pub fn fill<'a>(&'a self) -> Cow<'a, EventTree> {
+    /// Like `fill`, but applies `policy` if a counter overflows.
+    pub fn fill_with(&self, policy: OverflowPolicy) -> Result<Cow<'_, EventTree<N>>, Error> {
         if self.i == IdTree::zero() {
-            Cow::Borrowed(&self.e)
+            Ok(Cow::Borrowed(&self.e))
         } else if self.i == IdTree::one() {
-            Cow::Owned(EventTree::leaf(self.e.max()))
+            Ok(Cow::Owned(EventTree::new_leaf(self.e.max_with(policy)?)))
         } else if let EventTree::Leaf { .. } = self.e {
-            Cow::Borrowed(&self.e)
-        } else {
-            if let IdTree::Node {
-                left: ref i_left,
-                right: ref i_right,
-            } = self.i
+            Ok(Cow::Borrowed(&self.e))
+        } else if let IdTree::Node {
+            left: ref i_left,
+            right: ref i_right,
+        } = self.i
+        {
+            if let EventTree::Node {
+                n,
+                left: ref e_left,
+                right: ref e_right,
+            } = self.e
             {
-                if let EventTree::Node {
-                    n,
-                    left: ref e_left,
-                    right: ref e_right,
-                } = self.e
-                {
-                    if i_left.as_ref() == &IdTree::one() {
-                        let eprime_right =
-                            Stamp::new(i_right.as_ref().clone(), e_right.as_ref().clone())
-                                .fill()
-                                .into_owned();
-                        let new_left = EventTree::leaf(cmp::max(e_left.max(), eprime_right.min()));
-                        Cow::Owned(
-                            EventTree::node(n, Box::new(new_left), Box::new(eprime_right)).norm(),
-                        )
-                    } else if i_right.as_ref() == &IdTree::one() {
-                        let eprime_left =
-                            Stamp::new(i_left.as_ref().clone(), e_left.as_ref().clone())
-                                .fill()
-                                .into_owned();
-                        let new_right = EventTree::leaf(cmp::max(e_right.max(), eprime_left.min()));
-                        Cow::Owned(
-                            EventTree::node(n, Box::new(eprime_left), Box::new(new_right)).norm(),
-                        )
-                    } else {
-                        let new_left = Stamp::new(i_left.as_ref().clone(), e_left.as_ref().clone())
-                            .fill()
+                if i_left.as_ref() == &IdTree::one() {
+                    let eprime_right =
+                        Stamp::new(i_right.as_ref().clone(), e_right.as_ref().clone())
+                            .fill_with(policy)?
                             .into_owned();
-                        let new_right =
-                            Stamp::new(i_right.as_ref().clone(), e_right.as_ref().clone())
-                                .fill()
-                                .into_owned();
-                        Cow::Owned(
-                            EventTree::node(n, Box::new(new_left), Box::new(new_right)).norm(),
-                        )
-                    }
+                    let new_left = EventTree::new_leaf(cmp::max(
+                        e_left.max_with(policy)?,
+                        eprime_right.min_with(policy)?,
+                    ));
+                    Ok(Cow::Owned(
+                        EventTree::new_node(n, Box::new(new_left), Box::new(eprime_right))
+                            .norm_with(policy)?,
+                    ))
+                } else if i_right.as_ref() == &IdTree::one() {
+                    let eprime_left = Stamp::new(i_left.as_ref().clone(), e_left.as_ref().clone())
+                        .fill_with(policy)?
+                        .into_owned();
+                    let new_right = EventTree::new_leaf(cmp::max(
+                        e_right.max_with(policy)?,
+                        eprime_left.min_with(policy)?,
+                    ));
+                    Ok(Cow::Owned(
+                        EventTree::new_node(n, Box::new(eprime_left), Box::new(new_right))
+                            .norm_with(policy)?,
+                    ))
                 } else {
-                    unreachable!()
+                    let new_left = Stamp::new(i_left.as_ref().clone(), e_left.as_ref().clone())
+                        .fill_with(policy)?
+                        .into_owned();
+                    let new_right = Stamp::new(i_right.as_ref().clone(), e_right.as_ref().clone())
+                        .fill_with(policy)?
+                        .into_owned();
+                    Ok(Cow::Owned(
+                        EventTree::new_node(n, Box::new(new_left), Box::new(new_right))
+                            .norm_with(policy)?,
+                    ))
                 }
             } else {
                 unreachable!()
             }
+        } else {
+            unreachable!()
         }
     }
 
     // returns event tree and cost
-    pub fn grow(&self) -> (EventTree, Cost) {
-        match self.e {
-            EventTree::Leaf { n } => {
-                if self.i == IdTree::one() {
-                    (EventTree::leaf(n + 1), Cost::zero())
-                } else {
-                    let new_e = EventTree::node(
-                        n,
-                        Box::new(EventTree::zero()),
-                        Box::new(EventTree::zero()),
-                    );
-                    let (eprime, c) = Stamp::new(self.i.clone(), new_e).grow();
-                    (eprime, c.shift())
-                }
-            }
-            EventTree::Node {
-                n,
-                left: ref e_left,
-                right: ref e_right,
-            } => {
-                if let IdTree::Node {
-                    left: ref i_left,
-                    right: ref i_right,
-                } = self.i
-                {
-                    if **i_left == IdTree::zero() {
-                        let (eprime_right, c_right) =
-                            Stamp::new(i_right.as_ref().clone(), e_right.as_ref().clone()).grow();
-                        (
-                            EventTree::node(n, e_left.clone(), Box::new(eprime_right)),
-                            c_right + 1,
-                        )
-                    } else if **i_right == IdTree::zero() {
-                        let (eprime_left, c_left) =
-                            Stamp::new(*i_left.clone(), *e_left.clone()).grow();
-                        (
-                            EventTree::node(n, Box::new(eprime_left), e_right.clone()),
-                            c_left + 1,
-                        )
-                    } else {
-                        let (eprime_right, c_right) =
-                            Stamp::new(*i_right.clone(), *e_right.clone()).grow();
-                        let (eprime_left, c_left) =
-                            Stamp::new(*i_left.clone(), *e_left.clone()).grow();
-                        if c_left < c_right {
-                            (
-                                EventTree::node(n, Box::new(eprime_left), e_right.clone()),
-                                c_left + 1,
-                            )
-                        } else {
-                            (
-                                EventTree::node(n, e_left.clone(), Box::new(eprime_right)),
-                                c_right + 1,
-                            )
-                        }
-                    }
-                } else {
-                    // corrupted tree?
-                    unreachable!()
-                }
-            }
+    pub fn grow(&self) -> (EventTree<N>, Cost) {
+        self.grow_with(OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+
+    /// Like `grow`, but applies `policy` if the counter overflows.
+    ///
+    /// Fails with `AnonymousStamp` if the stamp owns no part of the interval,
+    /// or `NonNormalIdTree` if its id is not normalised.
+    pub fn grow_with(&self, policy: OverflowPolicy) -> Result<(EventTree<N>, Cost), Error> {
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+        let mut eprime = self.e.clone();
+        let cost = eprime.grow_with_strategy(&self.i, &MinimalGrowth, policy)?;
+        Ok((eprime, cost))
+    }
+
+    /// Like `event`, but applies `policy` if the counter overflows.
+    ///
+    /// Fails with `AnonymousStamp` if the stamp owns no part of the interval.
+    pub fn event_with(&self, policy: OverflowPolicy) -> Result<Stamp<N>, Error> {
+        self.event_with_strategy(&MinimalGrowth, policy)
+    }
+
+    /// Like `event_with`, but lets `strategy` pick which subtree to grow
+    /// when no counter can be filled instead.
+    pub fn event_with_strategy(
+        &self,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<Stamp<N>, Error> {
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+
+        let filled_e = self.fill_with(policy)?;
+
+        if filled_e.as_ref() != &self.e {
+            Ok(self.with_parts(self.i.clone(), filled_e.into_owned()))
+        } else {
+            let mut eprime = self.e.clone();
+            eprime.grow_with_strategy(&self.i, strategy, policy)?;
+
+            // a saturated counter does not grow, which can leave the tree unnormalised
+            let eprime = match policy {
+                OverflowPolicy::Error => eprime,
+                OverflowPolicy::Saturate => eprime.norm_with(policy)?,
+            };
+
+            Ok(self.with_parts(self.i.clone(), eprime))
         }
     }
 
+    /// Clones the id; `id` borrows it instead.
     pub fn id_tree(&self) -> IdTree {
         self.i.clone()
     }
-    pub fn event_tree(&self) -> EventTree {
+
+    /// Clones the event tree; `events` borrows it instead.
+    pub fn event_tree(&self) -> EventTree<N> {
         self.e.clone()
     }
+
+    pub fn id(&self) -> &IdTree {
+        &self.i
+    }
+
+    pub fn events(&self) -> &EventTree<N> {
+        &self.e
+    }
+
+    pub fn into_parts(self) -> (IdTree, EventTree<N>) {
+        (self.i, self.e)
+    }
 }
 
 pub trait Min<T> {
@@ -320,29 +827,17 @@ pub trait Max<T> {
     fn max(&self) -> T;
 }
 
-impl Min<u32> for EventTree {
-    fn min(&self) -> u32 {
-        match *self {
-            EventTree::Leaf { n } => n,
-            EventTree::Node {
-                n,
-                ref left,
-                ref right,
-            } => n + cmp::min(left.min(), right.min()),
-        }
+impl<N: Counter> Min<N> for EventTree<N> {
+    fn min(&self) -> N {
+        self.min_with(OverflowPolicy::Error)
+            .expect("event counter overflow")
     }
 }
 
-impl Max<u32> for EventTree {
-    fn max(&self) -> u32 {
-        match *self {
-            EventTree::Leaf { n } => n,
-            EventTree::Node {
-                n,
-                ref left,
-                ref right,
-            } => n + cmp::max(left.max(), right.max()),
-        }
+impl<N: Counter> Max<N> for EventTree<N> {
+    fn max(&self) -> N {
+        self.max_with(OverflowPolicy::Error)
+            .expect("event counter overflow")
     }
 }
 
@@ -354,9 +849,7 @@ impl Normalisable for IdTree {
     #[allow(non_shorthand_field_patterns)]
     fn norm(self) -> IdTree {
         match self {
-            IdTree::Leaf { i: _ } => {
-                return self;
-            }
+            IdTree::Leaf { i: _ } => self,
             IdTree::Node { left, right } => {
                 let norm_left = left.norm();
                 let norm_right = right.norm();
@@ -369,90 +862,218 @@ impl Normalisable for IdTree {
                     }
                 }
 
-                return IdTree::node(Box::new(norm_left), Box::new(norm_right));
+                IdTree::node(Box::new(norm_left), Box::new(norm_right))
             }
-        };
+        }
     }
 }
 
-impl Normalisable for EventTree {
-    fn norm(self) -> EventTree {
-        match self {
-            EventTree::Leaf { n: _ } => {
-                return self;
-            }
-            EventTree::Node { n, left, right } => {
-                let norm_left = left.norm();
-                let norm_right = right.norm();
+impl<N: Counter> Normalisable for EventTree<N> {
+    fn norm(self) -> EventTree<N> {
+        self.norm_with(OverflowPolicy::Error)
+            .expect("event counter overflow")
+    }
+}
 
-                if let EventTree::Leaf { n: m1 } = norm_left {
-                    if let EventTree::Leaf { n: m2 } = norm_right {
-                        if m1 == m2 {
-                            return EventTree::leaf(n + m1);
-                        }
-                    }
-                }
+impl<N: Counter> Normalisable for Stamp<N> {
+    fn norm(self) -> Stamp<N> {
+        let epoch = self.epoch;
+        Stamp {
+            i: self.i.norm(),
+            e: self.e.norm(),
+            epoch,
+        }
+    }
+}
 
-                // normalised trees have min == n
-                let min_left = norm_left.n();
-                let min_right = norm_right.n();
+pub trait LessThanOrEqual {
+    fn leq(&self, other: &Self) -> bool;
+}
 
-                let m = cmp::min(min_left, min_right);
+/// Stamps from different epochs are never ordered.
+impl<N: Counter> LessThanOrEqual for Stamp<N> {
+    fn leq(&self, other: &Stamp<N>) -> bool {
+        self.epoch == other.epoch && self.e.leq(&other.e)
+    }
+}
 
-                return EventTree::node(
-                    n + m,
-                    Box::new(norm_left.sink(m)),
-                    Box::new(norm_right.sink(m)),
-                );
-            }
-        }
+impl<N: Counter> LessThanOrEqual for EventTree<N> {
+    fn leq(&self, other: &EventTree<N>) -> bool {
+        self.leq_lifted(0, other, 0)
     }
 }
 
-impl Normalisable for Stamp {
-    fn norm(self) -> Stamp {
-        Stamp::new(self.i.norm(), self.e.norm())
+impl<N: Counter> EventTree<N> {
+    // self lifted by base1 <= other lifted by base2, without building the lifted trees
+    fn leq_lifted(&self, base1: u128, other: &EventTree<N>, base2: u128) -> bool {
+        let m1 = lift_wide(base1, self.n());
+        let m2 = lift_wide(base2, other.n());
+
+        match (self, other) {
+            (EventTree::Leaf { .. }, _) => m1 <= m2,
+            (
+                EventTree::Node {
+                    left: left1,
+                    right: right1,
+                    ..
+                },
+                EventTree::Leaf { .. },
+            ) => {
+                m1 <= m2
+                    && left1.leq_lifted(m1, other, base2)
+                    && right1.leq_lifted(m1, other, base2)
+            }
+            (
+                EventTree::Node {
+                    left: left1,
+                    right: right1,
+                    ..
+                },
+                EventTree::Node {
+                    left: left2,
+                    right: right2,
+                    ..
+                },
+            ) => m1 <= m2 && left1.leq_lifted(m1, left2, m2) && right1.leq_lifted(m1, right2, m2),
+        }
     }
 }
 
-pub trait LessThanOrEqual {
-    fn leq(&self, other: &Self) -> bool;
+/// The causal relationship between two event histories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    /// The first history happened before the second.
+    Before,
+    /// The first history happened after the second.
+    After,
+    /// Both histories contain the same events.
+    Equal,
+    /// Neither history contains the other.
+    Concurrent,
 }
 
-impl LessThanOrEqual for Stamp {
-    fn leq(&self, other: &Stamp) -> bool {
-        self.e.leq(&other.e)
+impl Causality {
+    fn from_leq(leq: bool, geq: bool) -> Causality {
+        match (leq, geq) {
+            (true, true) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (false, false) => Causality::Concurrent,
+        }
+    }
+
+    pub fn to_ordering(self) -> Option<cmp::Ordering> {
+        match self {
+            Causality::Before => Some(cmp::Ordering::Less),
+            Causality::After => Some(cmp::Ordering::Greater),
+            Causality::Equal => Some(cmp::Ordering::Equal),
+            Causality::Concurrent => None,
+        }
     }
 }
 
-impl LessThanOrEqual for EventTree {
-    #[allow(non_shorthand_field_patterns)]
-    fn leq(&self, other: &EventTree) -> bool {
+impl<N: Counter> EventTree<N> {
+    /// Compares two event histories in a single traversal of both trees.
+    pub fn causality(&self, other: &EventTree<N>) -> Causality {
+        let (leq, geq) = self.compare(0, other, 0);
+        Causality::from_leq(leq, geq)
+    }
+
+    // returns (self <= other, other <= self) where each tree is lifted by its base.
+    // a leaf is treated as a node with zero children when compared against a node.
+    fn compare(&self, base1: u128, other: &EventTree<N>, base2: u128) -> (bool, bool) {
+        EventTree::compare_parts(self.parts(), base1, other.parts(), base2)
+    }
+
+    fn compare_parts(
+        (n1, children1): Parts<N>,
+        base1: u128,
+        (n2, children2): Parts<N>,
+        base2: u128,
+    ) -> (bool, bool) {
+        let m1 = lift_wide(base1, n1);
+        let m2 = lift_wide(base2, n2);
+        let mut leq = m1 <= m2;
+        let mut geq = m2 <= m1;
+
+        if children1.is_none() && children2.is_none() {
+            return (leq, geq);
+        }
+
+        let zero = (N::ZERO, None);
+        let (left1, right1) = children1.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+        let (left2, right2) = children2.map_or((zero, zero), |(l, r)| (l.parts(), r.parts()));
+
+        for (c1, c2) in [(left1, left2), (right1, right2)] {
+            if !leq && !geq {
+                break;
+            }
+            let (l, g) = EventTree::compare_parts(c1, m1, c2, m2);
+            leq &= l;
+            geq &= g;
+        }
+
+        (leq, geq)
+    }
+
+    fn parts(&self) -> Parts<'_, N> {
         match *self {
-            EventTree::Leaf { n: n1 } => match *other {
-                EventTree::Leaf { n: n2 } => n1 <= n2,
-                EventTree::Node { n: n2, .. } => n1 <= n2,
-            },
+            EventTree::Leaf { n } => (n, None),
             EventTree::Node {
-                n: n1,
-                left: ref left1,
-                right: ref right1,
-            } => match *other {
-                EventTree::Leaf { n: n2 } => {
-                    (n1 <= n2)
-                        && left1.clone().lift(n1).leq(&EventTree::leaf(n2))
-                        && right1.clone().lift(n1).leq(&EventTree::leaf(n2))
-                }
-                EventTree::Node {
-                    n: n2,
-                    left: ref left2,
-                    right: ref right2,
-                } => {
-                    (n1 <= n2)
-                        && left1.clone().lift(n1).leq(&left2.clone().lift(n2))
-                        && right1.clone().lift(n1).leq(&right2.clone().lift(n2))
-                }
-            },
+                n,
+                ref left,
+                ref right,
+            } => (n, Some((left, right))),
+        }
+    }
+}
+
+// a node's counter and children, or a leaf's counter
+type Parts<'a, N> = (N, Option<(&'a EventTree<N>, &'a EventTree<N>)>);
+
+// n lifted by base for comparisons, which need no overflow policy: no path
+// through a tree can hold enough counters to overflow a u128
+fn lift_wide<N: Counter>(base: u128, n: N) -> u128 {
+    base + u128::from(n.to_u64())
+}
+
+/// Orders event trees by causality, returning `None` for concurrent histories.
+///
+/// This agrees with `PartialEq` for normalised trees.
+impl<N: Counter> PartialOrd for EventTree<N> {
+    fn partial_cmp(&self, other: &EventTree<N>) -> Option<cmp::Ordering> {
+        self.causality(other).to_ordering()
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    /// Stamps from different epochs are `Concurrent`, as their counters
+    /// are relative to different cuts.
+    pub fn causality(&self, other: &Stamp<N>) -> Causality {
+        if self.epoch != other.epoch {
+            return Causality::Concurrent;
+        }
+        self.e.causality(&other.e)
+    }
+
+    pub fn happened_before(&self, other: &Stamp<N>) -> bool {
+        self.causality(other) == Causality::Before
+    }
+
+    pub fn concurrent_with(&self, other: &Stamp<N>) -> bool {
+        self.causality(other) == Causality::Concurrent
+    }
+}
+
+/// Orders stamps by the causality of their event histories.
+///
+/// Stamps with the same events but different ids are unordered, to agree
+/// with `==`; `causality` reports them as `Equal`.
+impl<N: Counter> PartialOrd for Stamp<N> {
+    fn partial_cmp(&self, other: &Stamp<N>) -> Option<cmp::Ordering> {
+        match self.causality(other).to_ordering() {
+            Some(cmp::Ordering::Equal) if self != other => None,
+            ordering => ordering,
         }
     }
 }
@@ -523,38 +1144,27 @@ pub trait Sum {
 }
 
 impl Sum for IdTree {
-    #[allow(non_shorthand_field_patterns)]
+    /// # Panics
+    ///
+    /// Panics if the ids overlap. Use `try_sum` for ids that may overlap, or
+    /// `try_sum_all` to sum several at once.
     fn sum(&self, other: &IdTree) -> IdTree {
-        if *self == IdTree::zero() {
-            return other.clone();
-        } else if *other == IdTree::zero() {
-            return self.clone();
-        }
-
-        if let IdTree::Node {
-            left: ref left1,
-            right: ref right1,
-        } = *self
-        {
-            if let IdTree::Node {
-                left: ref left2,
-                right: ref right2,
-            } = *other
-            {
-                let new_left = Box::new(left1.sum(left2));
-                let new_right = Box::new(right1.sum(right2));
-                return IdTree::node(new_left, new_right).norm();
-            } else {
-                // corrupted tree?
-                unreachable!();
-            }
-        } else {
-            // corrupted tree?
-            unreachable!();
+        match self.try_sum(other) {
+            Ok(i) => i,
+            Err(e) => panic!("{}", e),
         }
     }
 }
 
+/// The clock verbs from the paper.
+///
+/// The verbs panic on input they cannot handle: overlapping ids or stamps
+/// from different epochs in `join`, an anonymous stamp in `fork` or
+/// `event`, or a counter overflow. Such input is a bug when every stamp is
+/// local, but a stamp received from a peer can be anything, so put those
+/// through the `try_*` methods of each stamp type instead, such as
+/// `Stamp::try_join`, `Stamp::try_event` and `Stamp::try_fork`, which
+/// return an `Error`.
 pub trait IntervalTreeClock
 where
     Self: Sized,
@@ -569,157 +1179,2056 @@ where
     fn sync(&self, other: &Self) -> (Self, Self);
 }
 
-impl IntervalTreeClock for Stamp {
-    fn peek(&self) -> (Stamp, Stamp) {
-        let s1 = Stamp::new(IdTree::zero(), self.e.clone());
-        let s2 = Stamp::new(self.i.clone(), self.e.clone());
-        return (s1, s2);
+impl<N: Counter> IntervalTreeClock for Stamp<N> {
+    fn peek(&self) -> (Stamp<N>, Stamp<N>) {
+        let s1 = self.with_parts(IdTree::zero(), self.e.clone());
+        let s2 = self.clone();
+        (s1, s2)
     }
 
-    fn fork(&self) -> (Stamp, Stamp) {
+    /// Panics if the stamp is anonymous, whose fork would be two more
+    /// anonymous stamps; see `try_fork`.
+    fn fork(&self) -> (Stamp<N>, Stamp<N>) {
+        if self.is_anonymous() {
+            panic!("{}", Error::AnonymousStamp);
+        }
         if let IdTree::Node { left, right } = self.i.split() {
-            let s1 = Stamp::new(*left, self.e.clone());
-            let s2 = Stamp::new(*right, self.e.clone());
+            let s1 = self.with_parts(*left, self.e.clone());
+            let s2 = self.with_parts(*right, self.e.clone());
             (s1, s2)
         } else {
             unreachable!()
         }
     }
 
-    fn join(&self, other: &Stamp) -> Stamp {
+    /// # Panics
+    ///
+    /// Panics if the ids overlap, the stamps are from different epochs or
+    /// a counter overflows. Use `try_join` for stamps that may not be
+    /// disjoint, such as one restored from a backup, or `join_all` to join
+    /// several at once.
+    fn join(&self, other: &Stamp<N>) -> Stamp<N> {
+        if let Err(e) = self.same_epoch(other) {
+            panic!("{}", e);
+        }
         let sum_i = self.i.sum(&other.i);
         let join_e = self.e.join(&other.e);
-        Stamp::new(sum_i, join_e)
+        self.with_parts(sum_i, join_e)
     }
 
-    fn event(&self) -> Stamp {
-        let filled_e = self.fill();
-
-        if filled_e.as_ref() != &self.e {
-            Stamp::new(self.i.clone(), filled_e.into_owned())
-        } else {
-            let (eprime, _c) = self.grow();
-
-            Stamp::new(self.i.clone(), eprime)
+    /// Panics if the stamp is anonymous or the counter overflows; see `try_event`.
+    fn event(&self) -> Stamp<N> {
+        match self.event_with(OverflowPolicy::Error) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
         }
     }
 
-    fn send(&self) -> (Stamp, Stamp) {
+    fn send(&self) -> (Stamp<N>, Stamp<N>) {
         self.event().peek()
     }
 
-    fn receive(&self, other: &Stamp) -> Stamp {
+    fn receive(&self, other: &Stamp<N>) -> Stamp<N> {
         self.join(other).event()
     }
 
-    fn sync(&self, other: &Stamp) -> (Stamp, Stamp) {
+    fn sync(&self, other: &Stamp<N>) -> (Stamp<N>, Stamp<N>) {
         self.join(other).fork()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<N: Counter> Stamp<N> {
+    /// Like `fork`, but fails instead of panicking on an anonymous stamp, and
+    /// also on an invalid one.
+    pub fn try_fork(&self) -> Result<(Stamp<N>, Stamp<N>), Error> {
+        self.validate()?;
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+        let (s1, s2) = self.fork();
+        if s1.i.depth() > MAX_DEPTH || s2.i.depth() > MAX_DEPTH {
+            return Err(Error::DepthLimitExceeded);
+        }
+        Ok((s1, s2))
+    }
 
-    #[test]
-    fn norm_id_one_is_one() {
-        let idt = IdTree::one();
-        let nidt = idt.norm();
-        assert_eq!(nidt, IdTree::one());
+    /// Like `join`, but fails instead of panicking on invalid or overlapping
+    /// stamps, or stamps from different epochs.
+    pub fn try_join(&self, other: &Stamp<N>) -> Result<Stamp<N>, Error> {
+        self.validate()?;
+        other.validate()?;
+        self.same_epoch(other)?;
+        let sum_i = self.i.try_sum(&other.i)?;
+        let join_e = self.e.join_with(&other.e, OverflowPolicy::Error)?;
+        Ok(self.with_parts(sum_i, join_e))
     }
 
-    #[test]
-    fn norm_id_zero_is_zero() {
-        let idt = IdTree::zero();
-        let nidt = idt.norm();
-        assert_eq!(nidt, IdTree::zero());
+    /// Like `event`, but fails instead of panicking on an invalid or anonymous
+    /// stamp, or when the counter would overflow.
+    pub fn try_event(&self) -> Result<Stamp<N>, Error> {
+        self.validate()?;
+        self.event_with(OverflowPolicy::Error)
     }
+}
 
-    #[test]
-    fn norm_id_0_0_is_0() {
-        let idt = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::zero()));
-        let nidt = idt.norm();
-        assert_eq!(nidt, IdTree::zero());
+/// In-place and consuming verbs, which reuse the stamp's existing boxes
+/// rather than building new trees.
+impl<N: Counter> Stamp<N> {
+    /// Like `event`, but updates the stamp in place.
+    ///
+    /// Panics if the stamp is anonymous or the counter overflows.
+    pub fn event_mut(&mut self) {
+        if let Err(e) = self.event_mut_with_strategy(&MinimalGrowth, OverflowPolicy::Error) {
+            panic!("{}", e);
+        }
     }
 
-    #[test]
-    fn norm_id_1_1_is_1() {
-        let idt = IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::one()));
-        let nidt = idt.norm();
-        assert_eq!(nidt, IdTree::one());
+    /// Like `event_with_strategy`, but updates the stamp in place.
+    pub fn event_mut_with_strategy(
+        &mut self,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<(), Error> {
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+        if !self.e.fill_in_place(&self.i, policy)? {
+            self.e.grow_with_strategy(&self.i, strategy, policy)?;
+
+            // a saturated counter does not grow, which can leave the tree unnormalised
+            if policy == OverflowPolicy::Saturate {
+                let e = mem::replace(&mut self.e, EventTree::new_zero());
+                self.e = e.norm_with(policy)?;
+            }
+        }
+        Ok(())
     }
 
-    #[test]
-    fn norm_id_0_1_is_0_1() {
-        let idt = IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero()));
-        let nidt = idt.clone().norm();
-        assert_eq!(nidt, idt);
+    /// Like `join`, but updates the stamp in place.
+    ///
+    /// Panics if the ids overlap, the stamps are from different epochs or a
+    /// counter overflows.
+    pub fn join_mut(&mut self, other: &Stamp<N>) {
+        let joined = self
+            .same_epoch(other)
+            .and_then(|_| self.i.sum_in_place(&other.i))
+            .and_then(|_| {
+                self.e
+                    .join_in_place(N::ZERO, &other.e, N::ZERO, OverflowPolicy::Error)
+            });
+        if let Err(e) = joined {
+            panic!("{}", e);
+        }
     }
 
-    #[test]
-    fn norm_id_1_1_1_is_1() {
-        let idt = IdTree::node(
-            Box::new(IdTree::one()),
-            Box::new(IdTree::node(
-                Box::new(IdTree::one()),
-                Box::new(IdTree::one()),
-            )),
-        );
-        let nidt = idt.clone().norm();
-        assert_eq!(nidt, IdTree::one());
+    /// Like `fork`, but consumes the stamp; only the event tree is cloned.
+    ///
+    /// Panics if the stamp is anonymous.
+    pub fn into_fork(self) -> (Stamp<N>, Stamp<N>) {
+        if self.is_anonymous() {
+            panic!("{}", Error::AnonymousStamp);
+        }
+        let (i1, i2) = self.i.into_split();
+        let epoch = self.epoch;
+        let s1 = Stamp {
+            i: i1,
+            e: self.e.clone(),
+            epoch,
+        };
+        let s2 = Stamp {
+            i: i2,
+            e: self.e,
+            epoch,
+        };
+        (s1, s2)
     }
 
-    // (2, 1, 1) ~=~ 3
-    #[test]
-    fn norm_e_one() {
-        let et = EventTree::node(
-            2,
-            Box::new(EventTree::leaf(1)),
-            Box::new(EventTree::leaf(1)),
-        );
-        let net = et.clone().norm();
-        assert_eq!(net, EventTree::leaf(3));
+    /// Like `join`, but consumes both stamps and reuses this one's trees.
+    pub fn into_join(mut self, other: Stamp<N>) -> Stamp<N> {
+        self.join_mut(&other);
+        self
     }
+}
 
-    // (2, (2, 1, 0), 3) ~=~ (4, (0, 1, 0), 1)
-    #[test]
-    fn norm_e_two() {
-        let a = Box::new(EventTree::node(
-            2,
-            Box::new(EventTree::leaf(1)),
-            Box::new(EventTree::leaf(0)),
-        ));
-        let b = Box::new(EventTree::leaf(3));
-        let et = EventTree::node(2, a, b);
+impl<N: Counter> Stamp<N> {
+    /// Gives up this replica's identity, returning its id for a survivor's
+    /// `absorb_id` and its events for the survivor to join.
+    pub fn retire(self) -> (IdTree, EventTree<N>) {
+        self.into_parts()
+    }
 
-        let expected_a = Box::new(EventTree::node(
-            0,
-            Box::new(EventTree::leaf(1)),
-            Box::new(EventTree::leaf(0)),
-        ));
-        let expected_b = Box::new(EventTree::leaf(1));
-        let expected = EventTree::node(4, expected_a, expected_b);
+    /// Takes over the id of a retired replica.
+    ///
+    /// Fails if either id is invalid or the donated id overlaps this one.
+    /// Only the id moves: join the retired events too unless this stamp has
+    /// already seen them, or new events here may not dominate old ones.
+    pub fn absorb_id(&self, retired: IdTree) -> Result<Stamp<N>, Error> {
+        self.validate()?;
+        retired.validate()?;
+        let i = self.i.try_sum(&retired)?;
+        Ok(self.with_parts(i, self.e.clone()))
+    }
+}
 
-        let net = et.norm();
+impl<N: Counter> Stamp<N> {
+    /// The history every one of `stamps` has seen, or `None` if there are
+    /// no stamps.
+    ///
+    /// Given every live replica, events at or below this cut are causally
+    /// stable: nothing concurrent with them can still be produced.
+    ///
+    /// Fails if any stamp is invalid or the stamps are from different
+    /// epochs, as happens while replicas are rebasing. The minimum itself
+    /// cannot overflow, but lifting a subtree to compare it can, so this
+    /// also fails if a path through an event tree sums past the counter's
+    /// maximum.
+    pub fn meet_events<'a, I>(stamps: I) -> Result<Option<EventTree<N>>, Error>
+    where
+        I: IntoIterator<Item = &'a Stamp<N>>,
+        N: 'a,
+    {
+        let mut stamps = stamps.into_iter();
+        let first = match stamps.next() {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        first.validate()?;
+        let mut cut = first.e.clone();
+        for s in stamps {
+            s.validate()?;
+            first.same_epoch(s)?;
+            cut = cut.meet_with(&s.e, OverflowPolicy::Error)?;
+        }
+        Ok(Some(cut))
+    }
+}
 
-        assert_eq!(net, expected);
+impl IdTree {
+    /// Sums any number of ids in one pass, failing if any two overlap.
+    /// Expects normalised ids.
+    pub fn try_sum_all<'a, I>(ids: I) -> Result<IdTree, Error>
+    where
+        I: IntoIterator<Item = &'a IdTree>,
+    {
+        let mut ids: Vec<&IdTree> = ids.into_iter().collect();
+        IdTree::sum_all_from(&mut ids, 0)
     }
 
-    #[test]
-    fn split_test() {
-        assert_eq!(
-            IdTree::one().split(),
-            IdTree::node(
-                Box::new(IdTree::node(
-                    Box::new(IdTree::one()),
-                    Box::new(IdTree::zero())
-                )),
-                Box::new(IdTree::node(
-                    Box::new(IdTree::zero()),
-                    Box::new(IdTree::one())
-                ))
-            )
-        );
+    // sums ids[from..], using the rest of the vec as scratch for the children
+    fn sum_all_from(ids: &mut Vec<&IdTree>, from: usize) -> Result<IdTree, Error> {
+        let end = ids.len();
+        // normalised ids own nothing or everything only as leaves
+        let mut owners = ids[from..]
+            .iter()
+            .filter(|i| !matches!(i, IdTree::Leaf { i: false }));
+        let first = match owners.next() {
+            None => return Ok(IdTree::zero()),
+            Some(i) => *i,
+        };
+        if owners.next().is_none() {
+            return Ok(first.clone());
+        }
+        if ids[from..]
+            .iter()
+            .any(|i| matches!(i, IdTree::Leaf { i: true }))
+        {
+            return Err(Error::IdOverlap);
+        }
+
+        let mut halves = [IdTree::zero(), IdTree::zero()];
+        for (side, half) in halves.iter_mut().enumerate() {
+            for k in from..end {
+                if let IdTree::Node {
+                    ref left,
+                    ref right,
+                } = *ids[k]
+                {
+                    ids.push(if side == 0 { left } else { right });
+                }
+            }
+            *half = IdTree::sum_all_from(ids, end)?;
+            ids.truncate(end);
+        }
+        let [left, right] = halves;
+        let mut sum = IdTree::node(Box::new(left), Box::new(right));
+        sum.norm_node_in_place();
+        Ok(sum)
+    }
+}
+
+impl<N: Counter> EventTree<N> {
+    /// Joins any number of trees in one pass, without building the
+    /// intermediate trees a fold over `join` would. Returns the zero tree if
+    /// there are none.
+    ///
+    /// Panics if a counter overflows.
+    pub fn join_all<'a, I>(trees: I) -> EventTree<N>
+    where
+        I: IntoIterator<Item = &'a EventTree<N>>,
+        N: 'a,
+    {
+        EventTree::join_all_with(trees, OverflowPolicy::Error).expect("event counter overflow")
+    }
+
+    /// Like `join_all`, but applies `policy` if a counter overflows.
+    pub fn join_all_with<'a, I>(trees: I, policy: OverflowPolicy) -> Result<EventTree<N>, Error>
+    where
+        I: IntoIterator<Item = &'a EventTree<N>>,
+        N: 'a,
+    {
+        let mut items: Vec<(Parts<N>, N)> =
+            trees.into_iter().map(|e| (e.parts(), N::ZERO)).collect();
+        if items.is_empty() {
+            return Ok(EventTree::new_zero());
+        }
+        EventTree::join_all_from(&mut items, 0, policy)
+    }
+
+    // joins the lifted trees in items[from..], like combine_parts for many
+    // trees, using the rest of the vec as scratch for the children
+    fn join_all_from<'a>(
+        items: &mut Vec<(Parts<'a, N>, N)>,
+        from: usize,
+        policy: OverflowPolicy,
+    ) -> Result<EventTree<N>, Error> {
+        let end = items.len();
+        for item in &mut items[from..] {
+            item.1 = policy.add(item.0 .0, item.1)?;
+        }
+        let max = items[from..].iter().map(|item| item.1).max().unwrap();
+        if items[from..].iter().all(|item| item.0 .1.is_none()) {
+            return Ok(EventTree::new_leaf(max));
+        }
+
+        let n = items[from..].iter().map(|item| item.1).min().unwrap();
+        let zero = (N::ZERO, None);
+        let mut halves = [EventTree::new_zero(), EventTree::new_zero()];
+        for (side, half) in halves.iter_mut().enumerate() {
+            for k in from..end {
+                let ((_, children), m) = items[k];
+                let child =
+                    children.map_or(zero, |(l, r)| if side == 0 { l.parts() } else { r.parts() });
+                items.push((child, m - n));
+            }
+            *half = EventTree::join_all_from(items, end, policy)?;
+            items.truncate(end);
+        }
+        let [left, right] = halves;
+        EventTree::norm_node(n, left, right, policy)
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    /// Joins any number of stamps in one pass, failing if any is invalid, any
+    /// two ids overlap or the stamps are from different epochs. Returns an
+    /// anonymous zero stamp if there are none.
+    pub fn join_all<'a, I>(stamps: I) -> Result<Stamp<N>, Error>
+    where
+        I: IntoIterator<Item = &'a Stamp<N>>,
+        N: 'a,
+    {
+        let stamps: Vec<&Stamp<N>> = stamps.into_iter().collect();
+        for s in &stamps {
+            s.validate()?;
+            stamps[0].same_epoch(s)?;
+        }
+        let i = IdTree::try_sum_all(stamps.iter().map(|s| &s.i))?;
+        let e = EventTree::join_all_with(stamps.iter().map(|s| &s.e), OverflowPolicy::Error)?;
+        let epoch = stamps.first().map_or(0, |s| s.epoch);
+        Ok(Stamp { i, e, epoch })
+    }
+}
+
+impl<N: Counter> Stamp<N> {
+    /// How many times the stamp has been rebased.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Subtracts a stable cut, such as `meet_events` over every replica,
+    /// from the event tree and moves the stamp to the next epoch.
+    ///
+    /// Every replica must rebase on the same cut before stamps are compared
+    /// or joined again: stamps of different epochs are never ordered, and
+    /// joining them fails.
+    ///
+    /// Panics if `stable` is not `leq` the stamp's events.
+    pub fn rebase(&self, stable: &EventTree<N>) -> Stamp<N> {
+        match self.try_rebase(stable) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `rebase`, but fails with `CounterUnderflow` if `stable` is not
+    /// `leq` the stamp's events, or `CounterOverflow` if the epoch would.
+    pub fn try_rebase(&self, stable: &EventTree<N>) -> Result<Stamp<N>, Error> {
+        let e = self.e.try_rebase(stable)?;
+        let epoch = self.epoch.checked_add(1).ok_or(Error::CounterOverflow)?;
+        Ok(Stamp {
+            i: self.i.clone(),
+            e,
+            epoch,
+        })
+    }
+
+    fn same_epoch(&self, other: &Stamp<N>) -> Result<(), Error> {
+        if self.epoch == other.epoch {
+            Ok(())
+        } else {
+            Err(Error::EpochMismatch)
+        }
+    }
+}
+
+// the finest cut fork_weighted makes, so that unit arithmetic fits in a u128
+const MAX_RESOLUTION: usize = 90;
+
+impl<N: Counter> Stamp<N> {
+    /// Forks into `n` stamps by repeated halving, so the ids form a
+    /// balanced tree at most `ceil(log2(n))` levels below this one's.
+    ///
+    /// Panics if `n` is zero or the stamp is anonymous; see `try_fork_n`.
+    pub fn fork_n(&self, n: usize) -> Vec<Stamp<N>> {
+        match self.try_fork_n(n) {
+            Ok(stamps) => stamps,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `fork_n`, but fails with `EmptyFork` if `n` is zero, and like
+    /// `try_fork` on an invalid or anonymous stamp.
+    pub fn try_fork_n(&self, n: usize) -> Result<Vec<Stamp<N>>, Error> {
+        self.check_forkable(n > 0)?;
+        let mut stamps = Vec::with_capacity(n);
+        self.clone().fork_into(n, &mut stamps);
+        check_fork_depth(&stamps)?;
+        Ok(stamps)
+    }
+
+    fn fork_into(self, n: usize, stamps: &mut Vec<Stamp<N>>) {
+        if n == 1 {
+            stamps.push(self);
+        } else {
+            let (s1, s2) = self.into_fork();
+            s1.fork_into(n - n / 2, stamps);
+            s2.fork_into(n / 2, stamps);
+        }
+    }
+
+    /// Forks into one stamp per weight, dividing the owned interval in
+    /// proportion to the weights.
+    ///
+    /// The interval is cut into the fewest equal units that give every
+    /// nonzero weight at least one, and each stamp gets a contiguous run of
+    /// units. A zero weight gets an anonymous stamp.
+    ///
+    /// Panics if the weights sum to zero or the stamp is anonymous; see
+    /// `try_fork_weighted`.
+    pub fn fork_weighted(&self, weights: &[u32]) -> Vec<Stamp<N>> {
+        match self.try_fork_weighted(weights) {
+            Ok(stamps) => stamps,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `fork_weighted`, but fails with `EmptyFork` if the weights sum to
+    /// zero, and like `try_fork` on an invalid or anonymous stamp.
+    pub fn try_fork_weighted(&self, weights: &[u32]) -> Result<Vec<Stamp<N>>, Error> {
+        let total: u64 = weights.iter().map(|&w| w as u64).sum();
+        self.check_forkable(total > 0)?;
+
+        let bits = (u64::BITS - (total - 1).leading_zeros()) as usize;
+        let resolution = cmp::min(self.i.depth() + bits, MAX_RESOLUTION);
+        let units = self.i.owned_units(0, resolution);
+        let ranges = apportion(weights, total, units);
+        // takes the leaves too small to hold a unit if no stamp has any units
+        let fallback = weights.iter().position(|&w| w > 0).unwrap();
+
+        let stamps: Vec<Stamp<N>> = (0..weights.len())
+            .map(|part| {
+                let owner = |offset: u128| {
+                    ranges
+                        .iter()
+                        .rposition(|&(start, end)| start < end && start <= offset)
+                        .unwrap_or(fallback)
+                };
+                let i = self
+                    .i
+                    .carve(0, resolution, &mut 0, ranges[part], &|offset| {
+                        owner(offset) == part
+                    });
+                self.with_parts(i, self.e.clone())
+            })
+            .collect();
+        check_fork_depth(&stamps)?;
+        Ok(stamps)
+    }
+
+    fn check_forkable(&self, parts: bool) -> Result<(), Error> {
+        self.validate()?;
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+        if !parts {
+            return Err(Error::EmptyFork);
+        }
+        Ok(())
+    }
+}
+
+fn check_fork_depth<N: Counter>(stamps: &[Stamp<N>]) -> Result<(), Error> {
+    if stamps.iter().any(|s| s.i.depth() > MAX_DEPTH) {
+        return Err(Error::DepthLimitExceeded);
+    }
+    Ok(())
+}
+
+// divides units between the weights by largest remainder, as contiguous ranges
+fn apportion(weights: &[u32], total: u64, units: u128) -> Vec<(u128, u128)> {
+    let quotas: Vec<(u128, u128)> = weights
+        .iter()
+        .map(|&w| {
+            let share = w as u128 * units;
+            (share / total as u128, share % total as u128)
+        })
+        .collect();
+
+    let assigned: u128 = quotas.iter().map(|&(q, _)| q).sum();
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    by_remainder.sort_by(|&a, &b| quotas[b].1.cmp(&quotas[a].1).then(a.cmp(&b)));
+
+    let mut counts: Vec<u128> = quotas.iter().map(|&(q, _)| q).collect();
+    for &k in by_remainder.iter().take((units - assigned) as usize) {
+        counts[k] += 1;
+    }
+
+    let mut start = 0;
+    counts
+        .iter()
+        .map(|&count| {
+            let range = (start, start + count);
+            start += count;
+            range
+        })
+        .collect()
+}
+
+impl IdTree {
+    // the owned units when the interval is cut into 2^resolution of them;
+    // leaves smaller than a unit count as none
+    fn owned_units(&self, depth: usize, resolution: usize) -> u128 {
+        match *self {
+            IdTree::Leaf { i: false } => 0,
+            IdTree::Leaf { i: true } if depth > resolution => 0,
+            IdTree::Leaf { i: true } => 1 << (resolution - depth),
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => left.owned_units(depth + 1, resolution) + right.owned_units(depth + 1, resolution),
+        }
+    }
+
+    // the part of this id covering owned units [start, end), where offset
+    // counts the owned units to the left. leaves smaller than a unit go
+    // whole to the part that owns_leaf picks for their offset.
+    fn carve(
+        &self,
+        depth: usize,
+        resolution: usize,
+        offset: &mut u128,
+        (start, end): (u128, u128),
+        owns_leaf: &dyn Fn(u128) -> bool,
+    ) -> IdTree {
+        match *self {
+            IdTree::Leaf { i: false } => IdTree::zero(),
+            IdTree::Leaf { i: true } if depth > resolution => IdTree::leaf(owns_leaf(*offset)),
+            IdTree::Leaf { i: true } => {
+                let size_log = resolution - depth;
+                let lo = *offset;
+                let hi = lo + (1 << size_log);
+                *offset = hi;
+                let clip = |x: u128| cmp::min(cmp::max(x, lo), hi) - lo;
+                IdTree::range(clip(start), clip(end), size_log)
+            }
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => {
+                let left = left.carve(depth + 1, resolution, offset, (start, end), owns_leaf);
+                let right = right.carve(depth + 1, resolution, offset, (start, end), owns_leaf);
+                IdTree::node(Box::new(left), Box::new(right)).norm()
+            }
+        }
+    }
+
+    // the id owning units [lo, hi) of an interval cut into 2^size_log units
+    fn range(lo: u128, hi: u128, size_log: usize) -> IdTree {
+        if lo >= hi {
+            IdTree::zero()
+        } else if lo == 0 && hi == 1 << size_log {
+            IdTree::one()
+        } else {
+            let half = 1 << (size_log - 1);
+            IdTree::node(
+                Box::new(IdTree::range(
+                    cmp::min(lo, half),
+                    cmp::min(hi, half),
+                    size_log - 1,
+                )),
+                Box::new(IdTree::range(
+                    cmp::max(lo, half) - half,
+                    cmp::max(hi, half) - half,
+                    size_log - 1,
+                )),
+            )
+        }
+    }
+}
+
+impl IdTree {
+    // like split, but returns the halves and keeps this id's boxes
+    fn into_split(self) -> (IdTree, IdTree) {
+        match self {
+            IdTree::Leaf { i: false } => (IdTree::zero(), IdTree::zero()),
+            IdTree::Leaf { i: true } => (
+                IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero())),
+                IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::one())),
+            ),
+            IdTree::Node {
+                mut left,
+                mut right,
+            } => {
+                if *left == IdTree::zero() {
+                    let (i1, i2) = mem::replace(&mut *right, IdTree::zero()).into_split();
+                    *right = i1;
+                    let second = IdTree::node(Box::new(IdTree::zero()), Box::new(i2));
+                    (IdTree::Node { left, right }, second)
+                } else if *right == IdTree::zero() {
+                    let (i1, i2) = mem::replace(&mut *left, IdTree::zero()).into_split();
+                    *left = i1;
+                    let second = IdTree::node(Box::new(i2), Box::new(IdTree::zero()));
+                    (IdTree::Node { left, right }, second)
+                } else {
+                    (
+                        IdTree::node(left, Box::new(IdTree::zero())),
+                        IdTree::node(Box::new(IdTree::zero()), right),
+                    )
+                }
+            }
+        }
+    }
+
+    fn sum_in_place(&mut self, other: &IdTree) -> Result<(), Error> {
+        match (&mut *self, other) {
+            (_, IdTree::Leaf { i: false }) => Ok(()),
+            (IdTree::Leaf { i: false }, _) => {
+                *self = other.clone();
+                Ok(())
+            }
+            (
+                IdTree::Node {
+                    left: left1,
+                    right: right1,
+                },
+                IdTree::Node {
+                    left: left2,
+                    right: right2,
+                },
+            ) => {
+                left1.sum_in_place(left2)?;
+                right1.sum_in_place(right2)?;
+                self.norm_node_in_place();
+                Ok(())
+            }
+            _ => Err(Error::IdOverlap),
+        }
+    }
+
+    // collapses a node whose children are equal leaves
+    fn norm_node_in_place(&mut self) {
+        if let IdTree::Node {
+            ref left,
+            ref right,
+        } = *self
+        {
+            if let (IdTree::Leaf { i: i1 }, IdTree::Leaf { i: i2 }) = (&**left, &**right) {
+                if i1 == i2 {
+                    *self = IdTree::leaf(*i1);
+                }
+            }
+        }
+    }
+}
+
+impl<N: Counter> EventTree<N> {
+    fn n_mut(&mut self) -> &mut N {
+        match *self {
+            EventTree::Leaf { ref mut n } => n,
+            EventTree::Node { ref mut n, .. } => n,
+        }
+    }
+
+    // normalises a node whose children are already normalised
+    fn norm_node_in_place(&mut self, policy: OverflowPolicy) -> Result<(), Error> {
+        if let EventTree::Node {
+            n,
+            ref mut left,
+            ref mut right,
+        } = *self
+        {
+            if let (EventTree::Leaf { n: m1 }, EventTree::Leaf { n: m2 }) = (&**left, &**right) {
+                if m1 == m2 {
+                    *self = EventTree::new_leaf(policy.add(n, *m1)?);
+                    return Ok(());
+                }
+            }
+
+            let m = cmp::min(left.n(), right.n());
+            *left.n_mut() = left.n() - m;
+            *right.n_mut() = right.n() - m;
+            *self.n_mut() = policy.add(n, m)?;
+        }
+        Ok(())
+    }
+
+    // sets self to the join of self lifted by lift1 and other lifted by lift2
+    fn join_in_place(
+        &mut self,
+        lift1: N,
+        other: &EventTree<N>,
+        lift2: N,
+        policy: OverflowPolicy,
+    ) -> Result<(), Error> {
+        let m1 = policy.add(self.n(), lift1)?;
+        let m2 = policy.add(other.n(), lift2)?;
+
+        if let EventTree::Leaf { n } = *self {
+            if let EventTree::Leaf { .. } = *other {
+                *self = EventTree::new_leaf(cmp::max(m1, m2));
+                return Ok(());
+            }
+            *self = EventTree::new_node(
+                n,
+                Box::new(EventTree::new_zero()),
+                Box::new(EventTree::new_zero()),
+            );
+        }
+
+        let zero = EventTree::new_zero();
+        let (left2, right2) = match *other {
+            EventTree::Leaf { .. } => (&zero, &zero),
+            EventTree::Node {
+                ref left,
+                ref right,
+                ..
+            } => (&**left, &**right),
+        };
+        let k = cmp::min(m1, m2);
+
+        if let EventTree::Node {
+            ref mut n,
+            ref mut left,
+            ref mut right,
+        } = *self
+        {
+            left.join_in_place(m1 - k, left2, m2 - k, policy)?;
+            right.join_in_place(m1 - k, right2, m2 - k, policy)?;
+            *n = k;
+        }
+        self.norm_node_in_place(policy)
+    }
+
+    // like fill, but returns whether the tree changed
+    fn fill_in_place(&mut self, i: &IdTree, policy: OverflowPolicy) -> Result<bool, Error> {
+        match (i, &mut *self) {
+            (IdTree::Leaf { i: false }, _) | (_, EventTree::Leaf { .. }) => Ok(false),
+            (IdTree::Leaf { i: true }, _) => {
+                *self = EventTree::new_leaf(self.max_with(policy)?);
+                Ok(true)
+            }
+            (
+                IdTree::Node {
+                    left: i_left,
+                    right: i_right,
+                },
+                EventTree::Node {
+                    left: e_left,
+                    right: e_right,
+                    ..
+                },
+            ) => {
+                let changed = if **i_left == IdTree::one() {
+                    let filled = e_right.fill_in_place(i_right, policy)?;
+                    let m = cmp::max(e_left.max_with(policy)?, e_right.min_with(policy)?);
+                    let raised = **e_left != EventTree::new_leaf(m);
+                    if raised {
+                        **e_left = EventTree::new_leaf(m);
+                    }
+                    filled || raised
+                } else if **i_right == IdTree::one() {
+                    let filled = e_left.fill_in_place(i_left, policy)?;
+                    let m = cmp::max(e_right.max_with(policy)?, e_left.min_with(policy)?);
+                    let raised = **e_right != EventTree::new_leaf(m);
+                    if raised {
+                        **e_right = EventTree::new_leaf(m);
+                    }
+                    filled || raised
+                } else {
+                    let filled_left = e_left.fill_in_place(i_left, policy)?;
+                    let filled_right = e_right.fill_in_place(i_right, policy)?;
+                    filled_left || filled_right
+                };
+
+                if changed {
+                    self.norm_node_in_place(policy)?;
+                }
+                Ok(changed)
+            }
+        }
+    }
+
+    // grows the tree in place along the sides strategy picks, returning the
+    // cost of the cheapest grow
+    fn grow_with_strategy(
+        &mut self,
+        i: &IdTree,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<Cost, Error> {
+        let mut plan = Vec::new();
+        let side = self.grow_plan(i, strategy, &mut plan)?;
+        self.grow_in_place(i, N::ZERO, &plan, 0, policy)?;
+        Ok(side.into_cost())
+    }
+
+    // summarises growing this subtree within i in one bottom-up pass. each
+    // node of i gets a (grow left, plan index of its right child) entry in
+    // preorder, so the grow itself only walks the chosen path. fails on an
+    // id that is not normalised.
+    fn grow_plan(
+        &self,
+        i: &IdTree,
+        strategy: &dyn GrowStrategy,
+        plan: &mut Vec<(bool, usize)>,
+    ) -> Result<GrowSide, Error> {
+        let k = plan.len();
+        plan.push((false, 0));
+        let zero = EventTree::new_zero();
+        let (e_left, e_right, split) = match (i, self) {
+            (IdTree::Leaf { i: false }, _) => return Ok(GrowSide::unowned(self.depth())),
+            (IdTree::Leaf { i: true }, EventTree::Leaf { .. }) => return Ok(GrowSide::owned_leaf()),
+            (_, EventTree::Leaf { .. }) => (&zero, &zero, true),
+            (_, EventTree::Node { left, right, .. }) => (&**left, &**right, false),
+        };
+        // an unfilled tree can have an owned id leaf over an event node
+        let (i_left, i_right) = i.halves();
+
+        let left = e_left.grow_plan(i_left, strategy, plan)?;
+        let right_k = plan.len();
+        let right = e_right.grow_plan(i_right, strategy, plan)?;
+        let (side, grow_left) = GrowSide::node(strategy, left, right, split)?;
+        plan[k] = (grow_left, right_k);
+        Ok(side)
+    }
+
+    // grows along the sides in plan from entry k, with counters below the
+    // tree lifted by base
+    fn grow_in_place(
+        &mut self,
+        i: &IdTree,
+        base: N,
+        plan: &[(bool, usize)],
+        k: usize,
+        policy: OverflowPolicy,
+    ) -> Result<(), Error> {
+        // grow_plan never picks a side the id owns none of
+        if *i == IdTree::zero() {
+            return Err(Error::NonNormalIdTree);
+        }
+
+        match self {
+            EventTree::Leaf { n } if *i == IdTree::one() => {
+                let value = policy.add(base, *n)?;
+                let step = policy.add(value, N::ONE)? - value;
+                *n = *n + step;
+                Ok(())
+            }
+            EventTree::Leaf { n } => {
+                let n = *n;
+                *self = EventTree::new_node(
+                    n,
+                    Box::new(EventTree::new_zero()),
+                    Box::new(EventTree::new_zero()),
+                );
+                self.grow_in_place(i, base, plan, k, policy)
+            }
+            EventTree::Node {
+                n,
+                left: e_left,
+                right: e_right,
+            } => {
+                let base = policy.add(base, *n)?;
+                let (i_left, i_right) = i.halves();
+                let (grow_left, right_k) = plan[k];
+                if grow_left {
+                    e_left.grow_in_place(i_left, base, plan, k + 1, policy)
+                } else {
+                    e_right.grow_in_place(i_right, base, plan, right_k, policy)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dyadic::Dyadic;
+    use std::str::FromStr;
+
+    // a small linear congruential generator keeps randomised tests deterministic
+    pub(crate) struct Lcg {
+        state: u64,
+    }
+
+    impl Lcg {
+        pub(crate) fn new(seed: u64) -> Lcg {
+            Lcg { state: seed }
+        }
+
+        // a number in 0..bound
+        pub(crate) fn next(&mut self, bound: usize) -> usize {
+            self.state = self
+                .state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.state >> 33) as usize % bound
+        }
+    }
+
+    // another representation of a stamp, checked against `Stamp` by the
+    // helpers below
+    pub(crate) trait Representation:
+        IntervalTreeClock + LessThanOrEqual + PartialEq + std::fmt::Debug + for<'a> From<&'a Stamp>
+    where
+        for<'a> Stamp: From<&'a Self>,
+    {
+        fn seed() -> Self;
+        fn causality(&self, other: &Self) -> Causality;
+        fn epoch(&self) -> u32;
+        fn validate(&self) -> Result<(), Error>;
+        fn validate_within(&self, max_depth: usize) -> Result<(), Error>;
+        fn try_join(&self, other: &Self) -> Result<Self, Error>;
+        fn try_event(&self) -> Result<Self, Error>;
+        fn event_with(&self, policy: OverflowPolicy) -> Result<Self, Error>;
+    }
+
+    pub(crate) fn check_converts<T: Representation>(s: &Stamp, t: &T)
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        assert_eq!(*s, Stamp::from(t));
+        assert_eq!(T::from(s), *t);
+    }
+
+    pub(crate) fn check_round_trip<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        for text in ["(1,0)", "((1,0),(0,1,0))", "(((0,1),0),(1,(0,1,0),4))"] {
+            let s: Stamp = Stamp::from_str(text).unwrap();
+            assert_eq!(s, Stamp::from(&T::from(&s)));
+        }
+    }
+
+    pub(crate) fn check_keeps_epoch<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let s = Stamp::seed().event().rebase(&EventTree::leaf(1));
+        let t = T::from(&s);
+        assert_eq!(1, t.epoch());
+        assert_eq!(s, Stamp::from(&t));
+        assert_eq!(s.event(), Stamp::from(&t.event()));
+        assert_eq!(s.fork(), {
+            let (a, b) = t.fork();
+            (Stamp::from(&a), Stamp::from(&b))
+        });
+
+        // stamps from different epochs are never ordered
+        let (a, b) = Stamp::seed().event().fork();
+        let (a, b) = (T::from(&a), T::from(&b.rebase(&EventTree::leaf(1))));
+        assert!(!a.leq(&b) && !b.leq(&a));
+        assert_eq!(Causality::Concurrent, a.causality(&b));
+    }
+
+    // panics with "stamps are from different epochs"
+    pub(crate) fn join_across_epochs<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let (a, b) = Stamp::seed().fork();
+        let b = b.rebase(&EventTree::zero());
+        T::from(&a).join(&T::from(&b));
+    }
+
+    pub(crate) fn check_compare_near_counter_max<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let a = T::from(&Stamp::from_str("(1,(4294967295,1,0))").unwrap());
+        let b = T::from(&Stamp::from_str("(0,4294967295)").unwrap());
+        assert!(!a.leq(&b));
+        assert_eq!(Causality::After, a.causality(&b));
+    }
+
+    pub(crate) fn check_fallible_verbs<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let (a, b) = Stamp::seed().fork();
+        let (ta, tb) = (T::from(&a), T::from(&b));
+        assert_eq!(Err(Error::IdOverlap), ta.try_join(&ta));
+        assert_eq!(Ok(Stamp::seed()), ta.try_join(&tb).map(|t| Stamp::from(&t)));
+        let rebased = T::from(&b.rebase(&EventTree::zero()));
+        assert_eq!(Err(Error::EpochMismatch), ta.try_join(&rebased));
+        assert_eq!(Err(Error::AnonymousStamp), ta.peek().0.try_event());
+
+        for text in [
+            "(1,4294967295)",
+            "((1,0),4294967295)",
+            "((0,1),(4294967294,0,1))",
+        ] {
+            let s: Stamp = Stamp::from_str(text).unwrap();
+            let t = T::from(&s);
+            assert_eq!(s.try_event(), t.try_event().map(|t| Stamp::from(&t)));
+            for policy in [OverflowPolicy::Error, OverflowPolicy::Saturate] {
+                assert_eq!(
+                    s.event_with(policy),
+                    t.event_with(policy).map(|t| Stamp::from(&t))
+                );
+            }
+        }
+
+        // not anonymous, but no side of the id is owned
+        let zeros = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::zero()));
+        let s: Stamp = Stamp::new(
+            IdTree::node(Box::new(zeros), Box::new(IdTree::zero())),
+            EventTree::zero(),
+        );
+        let t = T::from(&s);
+        assert_eq!(Err(Error::NonNormalIdTree), s.try_event());
+        assert_eq!(Err(Error::NonNormalIdTree), t.try_event().map(|_| ()));
+    }
+
+    pub(crate) fn check_validate<T: Representation>()
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let id = |l, r| IdTree::node(Box::new(l), Box::new(r));
+        let ev = |n, l, r| EventTree::node(n, Box::new(l), Box::new(r));
+        let stamps = [
+            Stamp::new(
+                id(IdTree::one(), IdTree::zero()),
+                ev(0, EventTree::leaf(1), EventTree::zero()),
+            ),
+            Stamp::new(
+                id(id(IdTree::zero(), IdTree::zero()), IdTree::one()),
+                EventTree::zero(),
+            ),
+            Stamp::new(IdTree::one(), ev(0, EventTree::leaf(1), EventTree::leaf(1))),
+            Stamp::new(
+                IdTree::one(),
+                ev(
+                    0,
+                    ev(1, EventTree::zero(), EventTree::leaf(2)),
+                    EventTree::leaf(1),
+                ),
+            ),
+            Stamp::new(
+                IdTree::one(),
+                ev(
+                    0,
+                    ev(0, EventTree::zero(), EventTree::leaf(2)),
+                    EventTree::leaf(1),
+                ),
+            ),
+        ];
+        for s in &stamps {
+            let t = T::from(s);
+            assert_eq!(s.validate(), t.validate());
+            assert_eq!(s.validate_within(1).is_ok(), t.validate_within(1).is_ok());
+            assert_eq!(s.try_event(), t.try_event().map(|t| Stamp::from(&t)));
+        }
+    }
+
+    // runs the same random forks, joins, peeks and events on both
+    pub(crate) fn check_matches_boxed_random<T: Representation>(seed: u64)
+    where
+        for<'a> Stamp: From<&'a T>,
+    {
+        let mut rng = Lcg::new(seed);
+
+        let mut boxed = vec![Stamp::seed()];
+        let mut other = vec![T::seed()];
+
+        for _ in 0..400 {
+            let k = rng.next(boxed.len());
+            match rng.next(4) {
+                0 if boxed.len() < 12 => {
+                    let (a, b) = boxed[k].fork();
+                    let (ta, tb) = other[k].fork();
+                    boxed[k] = a;
+                    other[k] = ta;
+                    boxed.push(b);
+                    other.push(tb);
+                }
+                1 if boxed.len() > 1 => {
+                    let s = boxed.swap_remove(k);
+                    let t = other.swap_remove(k);
+                    let j = rng.next(boxed.len());
+                    boxed[j] = boxed[j].join(&s);
+                    other[j] = other[j].join(&t);
+                }
+                2 => {
+                    let j = rng.next(boxed.len());
+                    let (msg, _) = boxed[j].peek();
+                    let (t_msg, _) = other[j].peek();
+                    boxed[k] = boxed[k].join(&msg);
+                    other[k] = other[k].join(&t_msg);
+                }
+                _ => {
+                    boxed[k] = boxed[k].event();
+                    other[k] = other[k].event();
+                }
+            }
+
+            for (s, t) in boxed.iter().zip(other.iter()) {
+                check_converts(s, t);
+            }
+            let (a, b) = (rng.next(boxed.len()), rng.next(boxed.len()));
+            assert_eq!(boxed[a].causality(&boxed[b]), other[a].causality(&other[b]));
+            assert_eq!(boxed[a].leq(&boxed[b]), other[a].leq(&other[b]));
+        }
+    }
+
+    #[test]
+    fn norm_id_one_is_one() {
+        let idt = IdTree::one();
+        let nidt = idt.norm();
+        assert_eq!(nidt, IdTree::one());
+    }
+
+    #[test]
+    fn norm_id_zero_is_zero() {
+        let idt = IdTree::zero();
+        let nidt = idt.norm();
+        assert_eq!(nidt, IdTree::zero());
+    }
+
+    #[test]
+    fn norm_id_0_0_is_0() {
+        let idt = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::zero()));
+        let nidt = idt.norm();
+        assert_eq!(nidt, IdTree::zero());
+    }
+
+    #[test]
+    fn norm_id_1_1_is_1() {
+        let idt = IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::one()));
+        let nidt = idt.norm();
+        assert_eq!(nidt, IdTree::one());
+    }
+
+    #[test]
+    fn norm_id_0_1_is_0_1() {
+        let idt = IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::zero()));
+        let nidt = idt.clone().norm();
+        assert_eq!(nidt, idt);
+    }
+
+    #[test]
+    fn norm_id_1_1_1_is_1() {
+        let idt = IdTree::node(
+            Box::new(IdTree::one()),
+            Box::new(IdTree::node(
+                Box::new(IdTree::one()),
+                Box::new(IdTree::one()),
+            )),
+        );
+        let nidt = idt.clone().norm();
+        assert_eq!(nidt, IdTree::one());
+    }
+
+    // (2, 1, 1) ~=~ 3
+    #[test]
+    fn norm_e_one() {
+        let et = EventTree::node(
+            2,
+            Box::new(EventTree::leaf(1)),
+            Box::new(EventTree::leaf(1)),
+        );
+        let net = et.clone().norm();
+        assert_eq!(net, EventTree::leaf(3));
+    }
+
+    // (2, (2, 1, 0), 3) ~=~ (4, (0, 1, 0), 1)
+    #[test]
+    fn norm_e_two() {
+        let a = Box::new(EventTree::node(
+            2,
+            Box::new(EventTree::leaf(1)),
+            Box::new(EventTree::leaf(0)),
+        ));
+        let b = Box::new(EventTree::leaf(3));
+        let et = EventTree::node(2, a, b);
+
+        let expected_a = Box::new(EventTree::node(
+            0,
+            Box::new(EventTree::leaf(1)),
+            Box::new(EventTree::leaf(0)),
+        ));
+        let expected_b = Box::new(EventTree::leaf(1));
+        let expected = EventTree::node(4, expected_a, expected_b);
+
+        let net = et.norm();
+
+        assert_eq!(net, expected);
+    }
+
+    #[test]
+    fn split_test() {
+        assert_eq!(
+            IdTree::one().split(),
+            IdTree::node(
+                Box::new(IdTree::node(
+                    Box::new(IdTree::one()),
+                    Box::new(IdTree::zero())
+                )),
+                Box::new(IdTree::node(
+                    Box::new(IdTree::zero()),
+                    Box::new(IdTree::one())
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn causality_test() {
+        let seed = Stamp::seed();
+        let (l, r) = seed.fork();
+        let le = l.event();
+        let re = r.event();
+        let j = le.join(&re);
+
+        assert_eq!(Causality::Equal, l.causality(&r));
+        assert_eq!(Causality::Before, seed.causality(&le));
+        assert_eq!(Causality::After, le.causality(&seed));
+        assert_eq!(Causality::Concurrent, le.causality(&re));
+        assert_eq!(Causality::Before, re.causality(&j));
+
+        assert!(le.concurrent_with(&re));
+        assert!(seed.happened_before(&j));
+        assert!(!j.happened_before(&le));
+    }
+
+    #[test]
+    fn causality_agrees_with_leq() {
+        let trees = [
+            EventTree::zero(),
+            EventTree::leaf(2),
+            EventTree::node(0, Box::new(EventTree::leaf(1)), Box::new(EventTree::zero())),
+            EventTree::node(0, Box::new(EventTree::zero()), Box::new(EventTree::leaf(3))),
+            EventTree::node(
+                1,
+                Box::new(EventTree::node(
+                    0,
+                    Box::new(EventTree::zero()),
+                    Box::new(EventTree::leaf(2)),
+                )),
+                Box::new(EventTree::zero()),
+            ),
+        ];
+        for a in trees.iter() {
+            for b in trees.iter() {
+                let expected = match (a.leq(b), b.leq(a)) {
+                    (true, true) => Causality::Equal,
+                    (true, false) => Causality::Before,
+                    (false, true) => Causality::After,
+                    (false, false) => Causality::Concurrent,
+                };
+                assert_eq!(expected, a.causality(b), "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn join_and_leq_with_lifted_subtrees() {
+        // (2, (0, 0, 3), 1) against (1, 4, (0, 2, 0))
+        let a = EventTree::node(
+            2,
+            Box::new(EventTree::node(
+                0,
+                Box::new(EventTree::zero()),
+                Box::new(EventTree::leaf(3)),
+            )),
+            Box::new(EventTree::leaf(1)),
+        );
+        let b = EventTree::node(
+            1,
+            Box::new(EventTree::leaf(4)),
+            Box::new(EventTree::node(
+                0,
+                Box::new(EventTree::leaf(2)),
+                Box::new(EventTree::zero()),
+            )),
+        );
+
+        // left: max((2,0,3), 5) = (5,0,0) = 5; right: max(3, (1,2,0)) = (3,0,0) = 3
+        let expected =
+            EventTree::node(3, Box::new(EventTree::leaf(2)), Box::new(EventTree::zero()));
+        let j = a.join(&b);
+        assert_eq!(expected, j);
+        assert_eq!(expected, b.join(&a));
+
+        assert!(!a.leq(&b));
+        assert!(!b.leq(&a));
+        assert!(a.leq(&j));
+        assert!(b.leq(&j));
+        assert!(!j.leq(&a));
+        assert!(EventTree::leaf(3).leq(&j));
+        assert!(!EventTree::leaf(4).leq(&j));
+        assert!(!j.leq(&EventTree::leaf(4)));
+        assert!(j.leq(&EventTree::leaf(5)));
+    }
+
+    #[test]
+    fn compare_near_counter_max() {
+        // the left leaf counts u32::MAX + 1 events
+        let a: Stamp = Stamp::from_str("(1,(4294967295,1,0))").unwrap();
+        let b = Stamp::from_str("(0,4294967295)").unwrap();
+        assert!(!a.leq(&b));
+        assert!(b.leq(&a));
+        assert_eq!(Causality::After, a.causality(&b));
+        assert_eq!(Causality::Equal, a.causality(&a.clone()));
+    }
+
+    #[test]
+    fn partial_ord_test() {
+        let (l, r) = Stamp::seed().fork();
+        let le = l.event();
+        let re = r.event();
+
+        assert!(l < le);
+        assert!(le > l);
+        assert_eq!(Causality::Equal, l.causality(&r));
+        assert_eq!(None, l.partial_cmp(&r));
+        assert_eq!(Some(cmp::Ordering::Equal), l.partial_cmp(&l.clone()));
+        assert!(le.partial_cmp(&re).is_none());
+        assert!(EventTree::leaf(1) < EventTree::leaf(2));
+    }
+
+    #[test]
+    fn validate_test() {
+        assert_eq!(Ok(()), Stamp::seed().validate());
+        assert_eq!(
+            Err(Error::NonNormalIdTree),
+            IdTree::node(
+                Box::new(IdTree::one()),
+                Box::new(IdTree::node(
+                    Box::new(IdTree::zero()),
+                    Box::new(IdTree::zero())
+                ))
+            )
+            .validate()
+        );
+        assert_eq!(
+            Err(Error::NonNormalEventTree),
+            EventTree::node(
+                2,
+                Box::new(EventTree::leaf(1)),
+                Box::new(EventTree::leaf(1))
+            )
+            .validate()
+        );
+        assert_eq!(
+            Err(Error::NonNormalEventTree),
+            EventTree::node(
+                2,
+                Box::new(EventTree::leaf(1)),
+                Box::new(EventTree::leaf(3))
+            )
+            .validate()
+        );
+        assert_eq!(
+            Ok(()),
+            EventTree::node(
+                2,
+                Box::new(EventTree::leaf(0)),
+                Box::new(EventTree::leaf(3))
+            )
+            .validate()
+        );
+        assert_eq!(
+            Err(Error::NonNormalIdTree),
+            Stamp::try_new(
+                IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::one())),
+                EventTree::zero()
+            )
+        );
+    }
+
+    #[test]
+    fn depth_limit_test() {
+        let mut i = IdTree::one();
+        for _ in 0..MAX_DEPTH {
+            i = IdTree::node(Box::new(i), Box::new(IdTree::zero()));
+        }
+        assert_eq!(MAX_DEPTH, i.depth());
+        assert_eq!(Ok(()), i.validate());
+
+        // the deepest leaf is a 1, so forking it adds a level
+        let s = Stamp::new(i, EventTree::zero());
+        assert_eq!(Err(Error::DepthLimitExceeded), s.try_fork().map(|_| ()));
+
+        let deeper = IdTree::node(Box::new(s.i), Box::new(IdTree::zero()));
+        assert_eq!(Err(Error::DepthLimitExceeded), deeper.validate());
+        assert_eq!(Ok(()), deeper.validate_within(MAX_DEPTH + 1));
+        assert_eq!(
+            Err(Error::DepthLimitExceeded),
+            IdTree::one().split().validate_within(1)
+        );
+
+        let mut e = EventTree::leaf(1);
+        for _ in 0..=MAX_DEPTH {
+            e = EventTree::node(0, Box::new(e), Box::new(EventTree::zero()));
+        }
+        assert_eq!(MAX_DEPTH + 1, e.depth());
+        assert_eq!(Err(Error::DepthLimitExceeded), e.validate());
+    }
+
+    #[test]
+    fn verbs_at_max_depth_fit_a_small_stack() {
+        let nested = |leaf: &str, node: &str, rest: &str| {
+            node.repeat(MAX_DEPTH) + leaf + &rest.repeat(MAX_DEPTH)
+        };
+        let text = format!(
+            "({},{})",
+            nested("1", "(", ",0)"),
+            nested("1", "(0,", ",0)")
+        );
+
+        // the limit must hold on the 2 MB default of spawned threads, in debug builds too
+        let verbs = std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(move || {
+                let s: Stamp = Stamp::from_str(&text).unwrap();
+                assert_eq!(Ok(()), s.validate());
+                let e = s.event();
+                let (a, b) = e.fork();
+                let j = a.event().join(&b.event());
+                assert!(s.leq(&j));
+                assert_eq!(Causality::Before, s.causality(&e));
+                assert_eq!(s.e, s.e.meet(&e.e));
+                assert_eq!(Ok(e.clone()), s.apply_delta(&e.delta_from(&s)));
+                assert_eq!(Ok(e.clone()), Stamp::decode_bits(&e.encode_bits()));
+                assert_eq!(Ok(e.clone()), Stamp::from_str(&e.to_string()));
+                assert!(e.e.segments().unwrap().count() > 0);
+                let mut m = e.rebase(&s.e);
+                m.event_mut();
+                let f = flat::FlatStamp::from(&e).event();
+                assert!(e.leq(&Stamp::from(&f)));
+            });
+        assert!(verbs.unwrap().join().is_ok());
+    }
+
+    #[test]
+    fn try_verbs_test() {
+        let bad = Stamp::new(
+            IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::zero())),
+            EventTree::zero(),
+        );
+        assert_eq!(Err(Error::NonNormalIdTree), bad.try_event());
+        assert_eq!(Err(Error::NonNormalIdTree), bad.try_fork());
+        assert_eq!(Err(Error::NonNormalIdTree), Stamp::seed().try_join(&bad));
+
+        let (a, b) = Stamp::seed().fork();
+        assert_eq!(Err(Error::IdOverlap), a.try_join(&a));
+        assert_eq!(Err(Error::IdOverlap), Stamp::seed().try_join(&b));
+        assert_eq!(Ok(Stamp::seed()), a.try_join(&b));
+
+        let (anon, _) = a.peek();
+        assert_eq!(Err(Error::AnonymousStamp), anon.try_event());
+        assert_eq!(Ok(a.event()), a.try_event());
+        assert_eq!(Ok(a.fork()), a.try_fork());
+    }
+
+    #[test]
+    #[should_panic(expected = "stamp has an anonymous id")]
+    fn fork_anonymous_panics() {
+        Stamp::seed().peek().0.fork();
+    }
+
+    #[test]
+    #[should_panic(expected = "stamp has an anonymous id")]
+    fn into_fork_anonymous_panics() {
+        Stamp::seed().peek().0.into_fork();
+    }
+
+    #[test]
+    fn anonymous_test() {
+        let (a, b) = Stamp::seed().fork();
+        let (anon, _) = a.event().peek();
+        assert!(anon.is_anonymous());
+        assert!(!a.is_anonymous());
+        assert_eq!(Stamp::anonymous(a.event().e), anon);
+
+        assert_eq!(Err(Error::AnonymousStamp), anon.try_fork());
+        assert_eq!(
+            Err(Error::AnonymousStamp),
+            anon.event_with(OverflowPolicy::Saturate)
+        );
+        assert_eq!(
+            Err(Error::AnonymousStamp),
+            anon.grow_with(OverflowPolicy::Error).map(|_| ())
+        );
+
+        // anonymous stamps still carry causal history to a receiver
+        let received = b.try_join(&anon).unwrap();
+        assert!(anon.leq(&received));
+        assert!(!received.is_anonymous());
+    }
+
+    #[test]
+    #[should_panic(expected = "stamp has an anonymous id")]
+    fn anonymous_event_panics_clearly() {
+        let (anon, _) = Stamp::seed().peek();
+        anon.event();
+    }
+
+    #[test]
+    fn non_normal_id_event_test() {
+        let zeros = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::zero()));
+        let id = IdTree::node(Box::new(zeros), Box::new(IdTree::zero()));
+        let mut s: Stamp = Stamp::new(id, EventTree::zero());
+        assert_eq!(
+            Err(Error::NonNormalIdTree),
+            s.event_with(OverflowPolicy::Error)
+        );
+        assert_eq!(
+            Err(Error::NonNormalIdTree),
+            s.event_mut_with_strategy(&MinimalGrowth, OverflowPolicy::Error)
+        );
+
+        // grow skips fill, so an owned leaf can sit over an event node
+        let s: Stamp = Stamp::from_str("(1,(0,1,0))").unwrap();
+        let (e, _) = s.grow_with(OverflowPolicy::Error).unwrap();
+        assert!(EventTree::from_str("(0,1,0)").unwrap().leq(&e));
+        assert_ne!(s.event_tree(), e);
+    }
+
+    #[test]
+    fn in_place_verbs_test() {
+        let mut rng = Lcg::new(3);
+
+        let mut stamps = vec![Stamp::seed()];
+        for _ in 0..300 {
+            let k = rng.next(stamps.len());
+            match rng.next(4) {
+                0 if stamps.len() < 10 => {
+                    let expected = stamps[k].fork();
+                    let (a, b) = stamps.swap_remove(k).into_fork();
+                    assert_eq!(expected, (a.clone(), b.clone()));
+                    stamps.push(a);
+                    stamps.push(b);
+                }
+                1 if stamps.len() > 1 => {
+                    let other = stamps.swap_remove(k);
+                    let j = rng.next(stamps.len());
+                    let expected = stamps[j].join(&other);
+                    let joined = stamps.swap_remove(j).into_join(other);
+                    assert_eq!(expected, joined);
+                    stamps.push(joined);
+                }
+                2 => {
+                    let j = rng.next(stamps.len());
+                    let (msg, _) = stamps[j].peek();
+                    let expected = stamps[k].join(&msg);
+                    stamps[k].join_mut(&msg);
+                    assert_eq!(expected, stamps[k]);
+                }
+                _ => {
+                    let expected = stamps[k].event();
+                    stamps[k].event_mut();
+                    assert_eq!(expected, stamps[k]);
+                }
+            }
+        }
+
+        let s = Stamp::seed().event();
+        assert_eq!(&IdTree::one(), s.id());
+        assert_eq!(&EventTree::leaf(1), s.events());
+        assert_eq!((IdTree::one(), EventTree::leaf(1)), s.into_parts());
+    }
+
+    // the fraction of the interval an id owns
+    fn measure(i: &IdTree) -> f64 {
+        match *i {
+            IdTree::Leaf { i } => i as u8 as f64,
+            IdTree::Node {
+                ref left,
+                ref right,
+            } => (measure(left) + measure(right)) / 2.0,
+        }
+    }
+
+    fn assert_partition(original: &Stamp, parts: &[Stamp]) {
+        let mut sum = IdTree::zero();
+        for part in parts {
+            assert_eq!(Ok(()), part.validate());
+            assert_eq!(original.e, part.e);
+            sum = sum.try_sum(&part.i).unwrap();
+        }
+        assert_eq!(original.i, sum);
+    }
+
+    #[test]
+    fn fork_n_test() {
+        let seed = Stamp::seed();
+        assert_eq!(vec![seed.clone()], seed.fork_n(1));
+        assert_eq!(vec![seed.fork().0, seed.fork().1], seed.fork_n(2));
+
+        let workers = seed.fork_n(12);
+        assert_eq!(12, workers.len());
+        assert_partition(&seed, &workers);
+        for w in &workers {
+            assert!(w.i.depth() <= 4);
+            assert!(measure(&w.i) >= 1.0 / 16.0);
+        }
+
+        let (_, s) = seed.event().fork();
+        let parts = s.fork_n(5);
+        assert_partition(&s, &parts);
+
+        let (anon, _) = s.peek();
+        assert_eq!(Err(Error::AnonymousStamp), anon.try_fork_n(2));
+        assert_eq!(Err(Error::EmptyFork), s.try_fork_n(0));
+        assert_eq!(Ok(parts), s.try_fork_n(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "fork into no stamps")]
+    fn fork_n_zero_panics() {
+        Stamp::seed().fork_n(0);
+    }
+
+    #[test]
+    fn fork_weighted_test() {
+        let seed = Stamp::seed();
+        let parts = seed.fork_weighted(&[1, 3]);
+        assert_eq!(IdTree::from_str("((1,0),0)").unwrap(), parts[0].i);
+        assert_eq!(IdTree::from_str("((0,1),1)").unwrap(), parts[1].i);
+
+        let parts = seed.fork_weighted(&[2, 0, 5, 1]);
+        assert_partition(&seed, &parts);
+        assert!(parts[1].is_anonymous());
+        for (part, w) in parts.iter().zip([2.0, 0.0, 5.0, 1.0]) {
+            assert_eq!(w / 8.0, measure(&part.i));
+        }
+
+        // the right half is cut into quarters, shared out as evenly as possible
+        let (_, right) = seed.fork();
+        let parts = right.fork_weighted(&[1, 1, 1]);
+        assert_partition(&right, &parts);
+        let shares: Vec<f64> = parts.iter().map(|p| measure(&p.i)).collect();
+        assert_eq!(vec![0.25, 0.125, 0.125], shares);
+
+        // leaves finer than the unit resolution go whole to one stamp
+        let mut deep = IdTree::one();
+        for _ in 0..100 {
+            deep = IdTree::node(Box::new(IdTree::zero()), Box::new(deep));
+        }
+        let deep = Stamp::new(deep, EventTree::zero());
+        let parts = deep.fork_weighted(&[1, 1]);
+        assert_partition(&deep, &parts);
+        assert_eq!(deep, parts[0]);
+
+        let (anon, _) = seed.peek();
+        assert_eq!(Err(Error::AnonymousStamp), anon.try_fork_weighted(&[1, 1]));
+        assert_eq!(Err(Error::EmptyFork), seed.try_fork_weighted(&[0, 0]));
+        assert_eq!(Err(Error::EmptyFork), seed.try_fork_weighted(&[]));
+    }
+
+    #[test]
+    fn retire_test() {
+        let replicas = Stamp::seed().event().fork_n(3);
+        let leaving = replicas[1].event();
+        let survivor = &replicas[2];
+
+        let (id, events) = leaving.clone().retire();
+        assert_eq!(
+            Err(Error::IdOverlap),
+            survivor.absorb_id(survivor.i.clone())
+        );
+        assert_eq!(
+            Err(Error::NonNormalIdTree),
+            survivor.absorb_id(IdTree::node(
+                Box::new(IdTree::one()),
+                Box::new(IdTree::one())
+            ))
+        );
+
+        let survivor = survivor.absorb_id(id).unwrap();
+        assert_eq!(replicas[1].i.sum(&replicas[2].i), survivor.i);
+        assert!(!leaving.leq(&survivor));
+
+        let survivor = survivor.join(&Stamp::anonymous(events));
+        assert!(leaving.leq(&survivor));
+        assert_eq!(IdTree::one(), replicas[0].i.sum(&survivor.i));
+    }
+
+    #[test]
+    fn overlap_test() {
+        let ids: Vec<IdTree> = [
+            "0",
+            "1",
+            "(1,0)",
+            "(0,1)",
+            "((0,1),0)",
+            "((1,0),1)",
+            "(0,(1,0))",
+        ]
+        .iter()
+        .map(|s| IdTree::from_str(s).unwrap())
+        .collect();
+        for a in &ids {
+            for b in &ids {
+                assert_eq!(a.overlaps(b), a.try_sum(b).is_err(), "{} {}", a, b);
+                assert_eq!(a.overlaps(b), b.overlaps(a));
+            }
+        }
+
+        // a replica restored from backup duplicates a live one
+        let (a, b) = Stamp::seed().fork();
+        let restored = a.clone();
+        assert!(a.i.overlaps(&restored.i));
+        assert!(!a.i.overlaps(&b.i));
+        assert_eq!(Err(Error::IdOverlap), a.event().try_join(&restored));
+    }
+
+    #[test]
+    #[should_panic(expected = "ids overlap")]
+    fn sum_overlap_panics_clearly() {
+        let (a, _) = Stamp::seed().fork();
+        a.join(&a);
+    }
+
+    #[test]
+    fn meet_test() {
+        let e = |s: &str| EventTree::<u32>::from_str(s).unwrap();
+        assert_eq!(e("(1,0,2)"), e("(1,0,3)").meet(&e("(1,2,2)")));
+        assert_eq!(e("(1,0,3)"), e("(1,0,3)").meet(&e("4")));
+        assert_eq!(
+            e("(0,0,(0,1,0))"),
+            e("(0,(0,2,1),3)").meet(&e("(0,0,(0,1,0))"))
+        );
+
+        let mut rng = Lcg::new(7);
+
+        let mut stamps = Stamp::seed().fork_n(6);
+        for _ in 0..200 {
+            let k = rng.next(stamps.len());
+            if rng.next(3) == 0 {
+                let (msg, _) = stamps[rng.next(stamps.len())].peek();
+                stamps[k].join_mut(&msg);
+            } else {
+                stamps[k].event_mut();
+            }
+
+            let (a, b) = (&stamps[k].e, &stamps[rng.next(stamps.len())].e);
+            let m = a.meet(b);
+            assert_eq!(Ok(()), m.validate());
+            assert!(m.leq(a) && m.leq(b));
+            assert_eq!(*a, a.meet(&a.join(b)));
+            let exp = cmp::max(a.depth(), b.depth()) as u32;
+            for j in 0..1u128 << exp {
+                let p = Dyadic::new(j, exp);
+                assert_eq!(
+                    Ok(cmp::min(a.value_at(p).unwrap(), b.value_at(p).unwrap())),
+                    m.value_at(p)
+                );
+            }
+        }
+
+        let cut = Stamp::meet_events(&stamps).unwrap().unwrap();
+        for s in &stamps {
+            assert!(cut.leq(&s.e));
+        }
+        let everything = stamps.iter().fold(EventTree::zero(), |e, s| e.join(&s.e));
+        assert_eq!(
+            cut,
+            Stamp::meet_events(&[Stamp::anonymous(everything), Stamp::anonymous(cut.clone())])
+                .unwrap()
+                .unwrap()
+        );
+        assert_eq!(Ok(None), Stamp::<u32>::meet_events(&[]));
+
+        let rebased = stamps[1].rebase(&EventTree::zero());
+        assert_eq!(
+            Err(Error::EpochMismatch),
+            Stamp::meet_events(&[stamps[0].clone(), rebased])
+        );
+        let past_max = Stamp::new(
+            IdTree::one(),
+            EventTree::node(
+                1,
+                Box::new(EventTree::node(
+                    0,
+                    Box::new(EventTree::leaf(u32::MAX)),
+                    Box::new(EventTree::zero()),
+                )),
+                Box::new(EventTree::zero()),
+            ),
+        );
+        assert_eq!(
+            Err(Error::CounterOverflow),
+            Stamp::meet_events(&[past_max, Stamp::seed()])
+        );
+    }
+
+    #[test]
+    fn join_all_test() {
+        let mut rng = Lcg::new(11);
+
+        let mut stamps = Stamp::seed().fork_n(9);
+        for _ in 0..300 {
+            let k = rng.next(stamps.len());
+            if rng.next(3) == 0 {
+                let (msg, _) = stamps[rng.next(stamps.len())].peek();
+                stamps[k].join_mut(&msg);
+            } else {
+                stamps[k].event_mut();
+            }
+
+            let count = 1 + rng.next(stamps.len());
+            let batch = &stamps[..count];
+            let folded = batch[1..]
+                .iter()
+                .fold(batch[0].clone(), |acc, s| acc.try_join(s).unwrap());
+            assert_eq!(Ok(folded.clone()), Stamp::join_all(batch));
+            assert_eq!(folded.e, EventTree::join_all(batch.iter().map(|s| &s.e)));
+        }
+
+        let everything = Stamp::join_all(&stamps).unwrap();
+        assert!(everything.i.is_full());
+
+        let s: Stamp = Stamp::new_seed();
+        assert_eq!(
+            Ok(Stamp::anonymous(EventTree::zero())),
+            Stamp::join_all(&[])
+        );
+        assert_eq!(EventTree::zero(), EventTree::<u32>::join_all(&[]));
+        assert_eq!(Ok(s.clone()), Stamp::join_all([&s]));
+
+        // an overlap between any two ids is caught, not only adjacent ones
+        let (a, b) = s.fork();
+        let (c, _) = a.fork();
+        assert_eq!(Err(Error::IdOverlap), Stamp::join_all(&[c.clone(), b, a]));
+        assert_eq!(
+            Err(Error::IdOverlap),
+            IdTree::try_sum_all(&[c.i.clone(), c.i])
+        );
+    }
+
+    #[test]
+    fn rebase_test() {
+        let e = |s: &str| EventTree::<u32>::from_str(s).unwrap();
+        assert_eq!(e("(0,0,1)"), e("(2,0,1)").rebase(&e("2")));
+        assert_eq!(e("(0,1,0)"), e("(1,1,2)").rebase(&e("(1,0,2)")));
+        assert_eq!(e("0"), e("(1,0,2)").rebase(&e("(1,0,2)")));
+        assert_eq!(
+            Err(Error::CounterUnderflow),
+            e("(1,0,2)").try_rebase(&e("2"))
+        );
+
+        let mut replicas = Stamp::seed().fork_n(5);
+        for k in 0..40 {
+            replicas[k % 5].event_mut();
+            let (msg, _) = replicas[(k * 3 + 1) % 5].peek();
+            replicas[k % 5].join_mut(&msg);
+        }
+        // after a full round of gossip everyone has seen some of everyone's events
+        let everything = Stamp::join_all(&replicas).unwrap().e;
+        for (k, r) in replicas.iter_mut().enumerate() {
+            r.join_mut(&Stamp::anonymous(everything.clone()));
+            if k % 2 == 0 {
+                r.event_mut();
+            }
+        }
+        let cut = Stamp::meet_events(&replicas).unwrap().unwrap();
+        assert!(cut.min() > 0);
+        let rebased: Vec<Stamp> = replicas.iter().map(|s| s.rebase(&cut)).collect();
+
+        for (a, a2) in replicas.iter().zip(&rebased) {
+            assert_eq!(1, a2.epoch());
+            assert_eq!(Ok(()), a2.validate());
+            assert!(a2.e.integral().unwrap() < a.e.integral().unwrap());
+            let exp = a.e.depth() as u32;
+            for j in 0..1u128 << exp {
+                let p = Dyadic::new(j, exp);
+                assert_eq!(
+                    Ok(a.e.value_at(p).unwrap() - cut.value_at(p).unwrap()),
+                    a2.e.value_at(p)
+                );
+            }
+            for (b, b2) in replicas.iter().zip(&rebased) {
+                assert_eq!(a.causality(b), a2.causality(b2));
+            }
+
+            // stamps from different epochs are never ordered or joined
+            assert!(!a.leq(a2) && !a2.leq(a));
+            assert_eq!(Causality::Concurrent, a.causality(a2));
+            assert_eq!(None, a.partial_cmp(a2));
+        }
+        let (msg, _) = rebased[1].peek();
+        assert_eq!(1, msg.epoch());
+        assert_eq!(Err(Error::EpochMismatch), replicas[0].try_join(&msg));
+        assert_eq!(
+            Err(Error::EpochMismatch),
+            Stamp::join_all([&rebased[0], &replicas[1]])
+        );
+        let joined = Stamp::join_all(&rebased).unwrap();
+        assert_eq!(1, joined.epoch());
+        assert_eq!(
+            EventTree::join_all(replicas.iter().map(|s| &s.e)).rebase(&cut),
+            joined.e
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "stamps are from different epochs")]
+    fn join_across_epochs_panics() {
+        let s = Stamp::seed();
+        let (a, b) = s.fork();
+        a.join(&b.rebase(&EventTree::zero()));
+    }
+
+    #[test]
+    fn id_set_algebra_test() {
+        let id = |s: &str| IdTree::from_str(s).unwrap();
+        let a = id("(1,(1,0))");
+        let b = id("((0,1),1)");
+
+        assert_eq!(id("((0,1),(1,0))"), a.intersect(&b));
+        assert_eq!(id("((1,0),0)"), a.difference(&b));
+        assert_eq!(id("(0,(0,1))"), b.difference(&a));
+        assert_eq!(id("(0,(0,1))"), a.complement());
+        assert_eq!(IdTree::one(), a.sum(&a.complement()));
+        assert_eq!(IdTree::zero(), a.intersect(&a.complement()));
+        assert_eq!(IdTree::zero(), a.difference(&a));
+        assert_eq!(a, a.intersect(&IdTree::one()));
+
+        // results are normalised even when the input is not
+        let ones = IdTree::node(Box::new(IdTree::one()), Box::new(IdTree::one()));
+        let unnormalised = IdTree::node(Box::new(ones), Box::new(IdTree::zero()));
+        assert_eq!(id("(0,1)"), unnormalised.complement());
+        assert_eq!(Ok(()), unnormalised.complement().validate());
+
+        assert!(id("((0,1),0)").is_subset_of(&a));
+        assert!(!b.is_subset_of(&a));
+        assert!(IdTree::zero().is_subset_of(&a));
+        assert!(a.is_subset_of(&IdTree::one()));
+
+        assert!(IdTree::zero().is_empty());
+        assert!(!a.is_empty());
+        assert!(IdTree::one().is_full());
+        assert!(!a.is_full());
+
+        // the part of the id space no live replica accounts for
+        let replicas = Stamp::seed().fork_n(4);
+        let live = replicas[0].i.sum(&replicas[2].i).sum(&replicas[3].i);
+        let missing = live.complement();
+        assert_eq!(replicas[1].i, missing);
+        for result in [a.intersect(&b), a.difference(&b), missing] {
+            assert_eq!(Ok(()), result.validate());
+        }
+    }
+
+    #[test]
+    fn overflow_test() {
+        let max = Stamp::new(IdTree::one(), EventTree::leaf(u32::MAX));
+        assert_eq!(Err(Error::CounterOverflow), max.try_event());
+        assert_eq!(Ok(max.clone()), max.event_with(OverflowPolicy::Saturate));
+
+        let (l, r) = Stamp::seed().fork();
+        let l = Stamp::new(l.id_tree(), EventTree::leaf(u32::MAX));
+        assert_eq!(Err(Error::CounterOverflow), l.try_event());
+        assert_eq!(Ok(l.clone()), l.event_with(OverflowPolicy::Saturate));
+        let mut saturated = l.clone();
+        assert_eq!(
+            Ok(()),
+            saturated.event_mut_with_strategy(&MinimalGrowth, OverflowPolicy::Saturate)
+        );
+        assert_eq!(l, saturated);
+        assert!(r.try_event().is_ok());
+
+        assert_eq!(
+            Err(Error::CounterOverflow),
+            EventTree::leaf(u32::MAX).lift_with(1, OverflowPolicy::Error)
+        );
+        assert_eq!(
+            Ok(EventTree::leaf(u32::MAX)),
+            EventTree::leaf(u32::MAX - 1).lift_with(5, OverflowPolicy::Saturate)
+        );
+
+        let deep = EventTree::node(
+            u32::MAX,
+            Box::new(EventTree::leaf(1)),
+            Box::new(EventTree::zero()),
+        );
+        assert_eq!(
+            Err(Error::CounterOverflow),
+            deep.max_with(OverflowPolicy::Error)
+        );
+        assert_eq!(Ok(u32::MAX), deep.max_with(OverflowPolicy::Saturate));
+
+        let a = EventTree::node(0, Box::new(EventTree::leaf(1)), Box::new(EventTree::zero()));
+        let b = EventTree::node(
+            5,
+            Box::new(EventTree::zero()),
+            Box::new(EventTree::leaf(u32::MAX)),
+        );
+        assert_eq!(
+            Err(Error::CounterOverflow),
+            a.join_with(&b, OverflowPolicy::Error)
+        );
+        assert!(a.join_with(&b, OverflowPolicy::Saturate).is_ok());
+    }
+
+    #[test]
+    fn underflow_test() {
+        assert_eq!(Err(Error::CounterUnderflow), EventTree::leaf(1).try_sink(2));
+        assert_eq!(Ok(EventTree::leaf(1)), EventTree::leaf(3).try_sink(2));
+    }
+
+    #[test]
+    fn u64_counter_test() {
+        let big = u32::MAX as u64;
+        let s: Stamp<u64> = Stamp::new(IdTree::one(), EventTree::new_leaf(big));
+        let s = s.event();
+        assert_eq!(EventTree::new_leaf(big + 1), s.event_tree());
+
+        let (l, r) = s.fork();
+        let le = l.event();
+        let re = r.event();
+        assert_eq!(Causality::Concurrent, le.causality(&re));
+
+        let j = le.join(&re);
+        assert_eq!(Ok(()), j.validate());
+        assert_eq!(big + 2, j.event_tree().max());
+        assert_eq!(big + 3, j.event().event_tree().min());
+        assert_eq!(Some(cmp::Ordering::Less), s.partial_cmp(&j));
     }
 
     #[test]
@@ -0,0 +1,21 @@
+use crate::counter::Counter;
+use crate::error::Error;
+
+/// What to do when an event counter would exceed its maximum value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Fail with `Error::CounterOverflow`.
+    #[default]
+    Error,
+    /// Clamp the counter at its maximum. Events recorded at the limit are lost.
+    Saturate,
+}
+
+impl OverflowPolicy {
+    pub fn add<N: Counter>(self, a: N, b: N) -> Result<N, Error> {
+        match self {
+            OverflowPolicy::Error => a.checked_add(b).ok_or(Error::CounterOverflow),
+            OverflowPolicy::Saturate => Ok(a.saturating_add(b)),
+        }
+    }
+}
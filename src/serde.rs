@@ -1,10 +1,20 @@
-// Mirror `IdTree`, `EventTree`, and `Stamp` types for nice json serialization in the form [4, [0, 1, 0], 1] etc
+// Mirror `IdTree`, `EventTree`, `Stamp` and `Cost` types for nice json serialization in the form [4, [0, 1, 0], 1] etc
+//
+// Trees are deserialized directly rather than through the mirrors, so a tree
+// nested deeper than `MAX_DEPTH` is rejected before any of it is built.
 
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, Error, IntoDeserializer, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::{EventTree, IdTree, Stamp};
+use crate::cost::Cost;
+use crate::counter::Counter;
+use crate::error;
+use crate::{EventTree, IdTree, Stamp, MAX_DEPTH};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 #[serde(untagged)]
 enum TupleIdTree {
     Leaf(u8),
@@ -23,58 +33,110 @@ impl From<&IdTree> for TupleIdTree {
     }
 }
 
-impl From<&TupleIdTree> for IdTree {
-    fn from(tuple_id_tree: &TupleIdTree) -> Self {
-        match tuple_id_tree {
-            TupleIdTree::Leaf(i) => IdTree::Leaf { i: *i == 1 }, // convert the integer back to bool
-            TupleIdTree::Node(left, right) => IdTree::Node {
-                left: Box::new(IdTree::from(left.as_ref())),
-                right: Box::new(IdTree::from(right.as_ref())),
-            },
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 #[serde(untagged)]
-enum TupleEventTree {
-    Leaf(u32),
-    Node(Box<TupleEventTree>, u32, Box<TupleEventTree>),
+enum TupleEventTree<N> {
+    Leaf(N),
+    Node(N, Box<TupleEventTree<N>>, Box<TupleEventTree<N>>),
 }
 
-impl From<&EventTree> for TupleEventTree {
-    fn from(event_tree: &EventTree) -> Self {
+impl<N: Counter> From<&EventTree<N>> for TupleEventTree<N> {
+    fn from(event_tree: &EventTree<N>) -> Self {
         match event_tree {
             EventTree::Leaf { n } => TupleEventTree::Leaf(*n),
             EventTree::Node { n, left, right } => TupleEventTree::Node(
-                Box::new(TupleEventTree::from(left.as_ref())),
                 *n,
+                Box::new(TupleEventTree::from(left.as_ref())),
                 Box::new(TupleEventTree::from(right.as_ref())),
             ),
         }
     }
 }
 
-impl From<&TupleEventTree> for EventTree {
-    fn from(tuple_event_tree: &TupleEventTree) -> Self {
-        match tuple_event_tree {
-            TupleEventTree::Leaf(n) => EventTree::Leaf { n: *n },
-            TupleEventTree::Node(left, n, right) => EventTree::Node {
-                n: *n,
-                left: Box::new(EventTree::from(left.as_ref())),
-                right: Box::new(EventTree::from(right.as_ref())),
-            },
+#[derive(Serialize)]
+struct TupleStamp<N> {
+    id: TupleIdTree,
+    event: TupleEventTree<N>,
+    // omitted until the first rebase
+    #[serde(skip_serializing_if = "is_zero")]
+    epoch: u32,
+}
+
+// the fields of a `TupleStamp`, deserialized with depth limited trees
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "N: Counter + Deserialize<'de>"))]
+struct StampFields<N> {
+    id: IdTree,
+    event: EventTree<N>,
+    #[serde(default)]
+    epoch: u32,
+}
+
+fn is_zero(epoch: &u32) -> bool {
+    *epoch == 0
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum TupleCost {
+    Small(u32),
+    Big(u32, Box<TupleCost>),
+}
+
+impl From<&Cost> for TupleCost {
+    fn from(cost: &Cost) -> Self {
+        match cost {
+            Cost::Small(n) => TupleCost::Small(*n),
+            Cost::Big(n, c) => TupleCost::Big(*n, Box::new(TupleCost::from(c.as_ref()))),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct TupleStamp {
-    id: TupleIdTree,
-    event: TupleEventTree,
+impl Serialize for IdTree {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TupleIdTree::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IdTree {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let tree = IdTreeSeed { depth: 0 }.deserialize(deserializer)?;
+        tree.validate().map_err(D::Error::custom)?;
+        Ok(tree)
+    }
+}
+
+impl<N: Counter + Serialize> Serialize for EventTree<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TupleEventTree::from(self).serialize(serializer)
+    }
+}
+
+impl<'de, N: Counter + Deserialize<'de>> Deserialize<'de> for EventTree<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let tree = EventTreeSeed {
+            depth: 0,
+            counter: PhantomData,
+        }
+        .deserialize(deserializer)?;
+        tree.validate().map_err(D::Error::custom)?;
+        Ok(tree)
+    }
 }
 
-impl Serialize for Stamp {
+impl<N: Counter + Serialize> Serialize for Stamp<N> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -82,26 +144,214 @@ impl Serialize for Stamp {
         TupleStamp {
             id: TupleIdTree::from(&self.i),
             event: TupleEventTree::from(&self.e),
+            epoch: self.epoch,
         }
         .serialize(serializer)
     }
 }
 
-impl<'de> Deserialize<'de> for Stamp {
+impl<'de, N: Counter + Deserialize<'de>> Deserialize<'de> for Stamp<N> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|TupleStamp { id, event }| Stamp {
-            i: IdTree::from(&id),
-            e: EventTree::from(&event),
-        })
+        let StampFields { id, event, epoch } = StampFields::deserialize(deserializer)?;
+        let stamp = Stamp {
+            i: id,
+            e: event,
+            epoch,
+        };
+        stamp.validate().map_err(D::Error::custom)?;
+        Ok(stamp)
+    }
+}
+
+impl Serialize for Cost {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TupleCost::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cost {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CostSeed { depth: 0 }.deserialize(deserializer)
+    }
+}
+
+// an id tree at depth, as 0, 1 or [left, right]
+struct IdTreeSeed {
+    depth: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for IdTreeSeed {
+    type Value = IdTree;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<IdTree, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
     }
 }
 
+impl<'de> Visitor<'de> for IdTreeSeed {
+    type Value = IdTree;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an id tree")
+    }
+
+    fn visit_u64<E: Error>(self, i: u64) -> Result<IdTree, E> {
+        match i {
+            0 => Ok(IdTree::zero()),
+            1 => Ok(IdTree::one()),
+            _ => Err(E::custom(format!("id tree leaf must be 0 or 1, got {}", i))),
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<IdTree, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        check_depth(self.depth + 1)?;
+        let child = || IdTreeSeed {
+            depth: self.depth + 1,
+        };
+        let left = next_element(&mut seq, child(), 0, &self)?;
+        let right = next_element(&mut seq, child(), 1, &self)?;
+        end_of_seq(seq, 2, &self)?;
+        Ok(IdTree::node(Box::new(left), Box::new(right)))
+    }
+}
+
+// an event tree at depth, as n or [n, left, right]
+struct EventTreeSeed<N> {
+    depth: usize,
+    counter: PhantomData<N>,
+}
+
+impl<'de, N: Counter + Deserialize<'de>> DeserializeSeed<'de> for EventTreeSeed<N> {
+    type Value = EventTree<N>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<EventTree<N>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, N: Counter + Deserialize<'de>> Visitor<'de> for EventTreeSeed<N> {
+    type Value = EventTree<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an event tree")
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<EventTree<N>, E> {
+        N::deserialize(n.into_deserializer()).map(EventTree::new_leaf)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<EventTree<N>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        check_depth(self.depth + 1)?;
+        let child = || EventTreeSeed {
+            depth: self.depth + 1,
+            counter: PhantomData,
+        };
+        let n = next_element(&mut seq, PhantomData::<N>, 0, &self)?;
+        let left = next_element(&mut seq, child(), 1, &self)?;
+        let right = next_element(&mut seq, child(), 2, &self)?;
+        end_of_seq(seq, 3, &self)?;
+        Ok(EventTree::new_node(n, Box::new(left), Box::new(right)))
+    }
+}
+
+// a cost at depth, as n or [n, cost]
+struct CostSeed {
+    depth: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for CostSeed {
+    type Value = Cost;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Cost, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for CostSeed {
+    type Value = Cost;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a cost")
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<Cost, E> {
+        u32::deserialize(n.into_deserializer()).map(Cost::Small)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Cost, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        check_depth(self.depth + 1)?;
+        let child = CostSeed {
+            depth: self.depth + 1,
+        };
+        let n = next_element(&mut seq, PhantomData::<u32>, 0, &self)?;
+        let c = next_element(&mut seq, child, 1, &self)?;
+        end_of_seq(seq, 2, &self)?;
+        Ok(Cost::Big(n, Box::new(c)))
+    }
+}
+
+fn next_element<'de, A, T>(
+    seq: &mut A,
+    seed: T,
+    index: usize,
+    expected: &dyn de::Expected,
+) -> Result<T::Value, A::Error>
+where
+    A: SeqAccess<'de>,
+    T: DeserializeSeed<'de>,
+{
+    seq.next_element_seed(seed)?
+        .ok_or_else(|| A::Error::invalid_length(index, expected))
+}
+
+fn end_of_seq<'de, A>(mut seq: A, len: usize, expected: &dyn de::Expected) -> Result<(), A::Error>
+where
+    A: SeqAccess<'de>,
+{
+    match seq.next_element::<de::IgnoredAny>()? {
+        Some(_) => Err(A::Error::invalid_length(len + 1, expected)),
+        None => Ok(()),
+    }
+}
+
+fn check_depth<E: Error>(depth: usize) -> Result<(), E> {
+    if depth > MAX_DEPTH {
+        return Err(E::custom(error::Error::DepthLimitExceeded));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::IntervalTreeClock;
     #[test]
     /// Expect that serializing the empty stamp gives the expected string and
     fn empty() {
@@ -133,8 +383,137 @@ mod tests {
             ),
         );
         let serialized = serde_json::to_string(&stamp).unwrap();
-        assert_eq!(serialized, "{\"id\":[[1,0],0],\"event\":[[1,1,0],0,0]}");
+        assert_eq!(serialized, "{\"id\":[[1,0],0],\"event\":[0,[1,1,0],0]}");
         let new_stamp: Stamp = serde_json::from_str(&serialized).unwrap();
         assert_eq!(stamp, new_stamp);
     }
+
+    #[test]
+    fn trees() {
+        let e = EventTree::node(
+            4,
+            Box::new(EventTree::node(
+                0,
+                Box::new(EventTree::leaf(1)),
+                Box::new(EventTree::zero()),
+            )),
+            Box::new(EventTree::leaf(1)),
+        );
+        assert_eq!(serde_json::to_string(&e).unwrap(), "[4,[0,1,0],1]");
+        assert_eq!(e, serde_json::from_str("[4,[0,1,0],1]").unwrap());
+
+        let i = IdTree::node(Box::new(IdTree::zero()), Box::new(IdTree::one()));
+        assert_eq!(serde_json::to_string(&i).unwrap(), "[0,1]");
+        assert_eq!(i, serde_json::from_str("[0,1]").unwrap());
+    }
+
+    #[test]
+    fn u64_counters() {
+        let stamp: Stamp<u64> = Stamp::new(IdTree::one(), EventTree::new_leaf(u32::MAX as u64 + 1));
+        let serialized = serde_json::to_string(&stamp).unwrap();
+        assert_eq!(serialized, "{\"id\":1,\"event\":4294967296}");
+        let new_stamp: Stamp<u64> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(stamp, new_stamp);
+        assert!(serde_json::from_str::<Stamp>(&serialized).is_err());
+    }
+
+    #[test]
+    fn epoch() {
+        let stamp = Stamp::seed().event().rebase(&EventTree::leaf(1));
+        let serialized = serde_json::to_string(&stamp).unwrap();
+        assert_eq!(serialized, "{\"id\":1,\"event\":0,\"epoch\":1}");
+        let new_stamp: Stamp = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(stamp, new_stamp);
+    }
+
+    #[test]
+    fn cost() {
+        let cost = ((Cost::zero() + 1).shift() + 2).shift();
+        assert_eq!(serde_json::to_string(&cost).unwrap(), "[0,[2,1]]");
+        assert_eq!(cost, serde_json::from_str("[0,[2,1]]").unwrap());
+        assert_eq!(Cost::zero() + 3, serde_json::from_str("3").unwrap());
+        assert!(serde_json::from_str::<Cost>("[1,2,3]").is_err());
+        assert!(serde_json::from_str::<Cost>("4294967296").is_err());
+    }
+
+    #[test]
+    fn invalid_id_leaf() {
+        assert!(serde_json::from_str::<IdTree>("[0,2]").is_err());
+        assert!(serde_json::from_str::<Stamp>("{\"id\":2,\"event\":0}").is_err());
+    }
+
+    #[test]
+    fn invalid_shape() {
+        assert!(serde_json::from_str::<EventTree>("[1,2]").is_err());
+        assert!(serde_json::from_str::<EventTree>("[[0,1,0],1,0]").is_err());
+        assert!(serde_json::from_str::<IdTree>("[0,1,0]").is_err());
+    }
+
+    #[test]
+    fn non_normal() {
+        for id in ["[[0,0],0]", "[1,1]"] {
+            let error = serde_json::from_str::<IdTree>(id).unwrap_err();
+            assert!(error.to_string().starts_with("id tree is not normalised"));
+            let stamp = format!("{{\"id\":{},\"event\":0}}", id);
+            assert!(serde_json::from_str::<Stamp>(&stamp).is_err());
+        }
+        for event in ["[2,1,1]", "[0,1,[0,2,2]]", "[0,1,1]"] {
+            let error = serde_json::from_str::<EventTree>(event).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("event tree is not normalised"));
+            let stamp = format!("{{\"id\":1,\"event\":{}}}", event);
+            assert!(serde_json::from_str::<Stamp>(&stamp).is_err());
+        }
+    }
+
+    // without serde_json's own recursion limit, which is lower than `MAX_DEPTH + 1`
+    fn from_str_unbounded<'a, T: Deserialize<'a>>(s: &'a str) -> serde_json::Result<T> {
+        let mut deserializer = serde_json::Deserializer::from_str(s);
+        deserializer.disable_recursion_limit();
+        T::deserialize(&mut deserializer)
+    }
+
+    #[test]
+    fn depth_limit() {
+        let id = |depth| "[".repeat(depth) + "1" + &",0]".repeat(depth);
+        let event = |depth| "[0,".repeat(depth) + "1" + &",0]".repeat(depth);
+        assert_eq!(
+            serde_json::from_str::<IdTree>(&id(MAX_DEPTH))
+                .unwrap()
+                .depth(),
+            MAX_DEPTH
+        );
+        assert_eq!(
+            serde_json::from_str::<EventTree>(&event(MAX_DEPTH))
+                .unwrap()
+                .depth(),
+            MAX_DEPTH
+        );
+
+        let cost = "[0,".repeat(MAX_DEPTH) + "1" + &"]".repeat(MAX_DEPTH);
+        assert!(serde_json::from_str::<Cost>(&cost).is_ok());
+
+        // rejected on the way down, so even absurdly deep input can't exhaust the stack
+        for depth in [MAX_DEPTH + 1, 1_000_000] {
+            let error = from_str_unbounded::<IdTree>(&id(depth)).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("tree exceeds the depth limit"));
+            let error = from_str_unbounded::<EventTree>(&event(depth)).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("tree exceeds the depth limit"));
+            let cost = "[0,".repeat(depth) + "1" + &"]".repeat(depth);
+            let error = from_str_unbounded::<Cost>(&cost).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("tree exceeds the depth limit"));
+            let stamp = format!("{{\"id\":1,\"event\":{}}}", event(depth));
+            let error = from_str_unbounded::<Stamp>(&stamp).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("tree exceeds the depth limit"));
+        }
+    }
 }
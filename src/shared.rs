@@ -0,0 +1,785 @@
+//! Stamps whose trees share structure through reference-counted nodes.
+//!
+//! Every subtree of a `SharedStamp` sits behind an `Arc`, so `peek` and the
+//! event trees of `fork` are reference count bumps, and `event` and `join`
+//! copy only the nodes on the paths they change. Counters below a node are
+//! relative to it, so lifting or sinking a shared subtree copies just its
+//! root. Stamps are `Send + Sync` and can be handed between threads.
+//!
+//! Enabled by the `shared` feature. `SharedStamp` implements
+//! `IntervalTreeClock` and converts to and from `Stamp`, keeping its epoch.
+//! As on `Stamp`, `try_join`, `try_event` and `event_with` return errors
+//! instead of panicking.
+
+use std::cmp;
+use std::sync::Arc;
+
+use crate::cost::{GrowSide, GrowStrategy, MinimalGrowth};
+use crate::counter::Counter;
+use crate::error::Error;
+use crate::overflow::OverflowPolicy;
+use crate::{
+    lift_wide, Causality, EventTree, IdTree, IntervalTreeClock, LessThanOrEqual, Stamp, MAX_DEPTH,
+};
+
+#[derive(Debug, PartialEq, Eq)]
+enum IdNode {
+    Leaf(bool),
+    Node(Arc<IdNode>, Arc<IdNode>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum EventNode<N> {
+    Leaf(N),
+    Node(N, Arc<EventNode<N>>, Arc<EventNode<N>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedStamp<N = u32> {
+    i: Arc<IdNode>,
+    e: Arc<EventNode<N>>,
+    // as for `Stamp`, stamps of different epochs are incomparable
+    epoch: u32,
+}
+
+impl SharedStamp {
+    pub fn seed() -> SharedStamp {
+        SharedStamp::new_seed()
+    }
+}
+
+impl<N: Counter> SharedStamp<N> {
+    /// Like `seed`, for any counter type.
+    pub fn new_seed() -> SharedStamp<N> {
+        SharedStamp {
+            i: Arc::new(IdNode::Leaf(true)),
+            e: Arc::new(EventNode::Leaf(N::ZERO)),
+            epoch: 0,
+        }
+    }
+
+    // a stamp in the same epoch as this one
+    fn with_parts(&self, i: Arc<IdNode>, e: Arc<EventNode<N>>) -> SharedStamp<N> {
+        SharedStamp {
+            i,
+            e,
+            epoch: self.epoch,
+        }
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        *self.i == IdNode::Leaf(false)
+    }
+
+    /// How many times the stamp this was converted from had been rebased.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Stamps from different epochs are `Concurrent`, as for `Stamp`.
+    pub fn causality(&self, other: &SharedStamp<N>) -> Causality {
+        if self.epoch != other.epoch {
+            return Causality::Concurrent;
+        }
+        let (leq, geq) = compare(&self.e, 0, &other.e, 0);
+        Causality::from_leq(leq, geq)
+    }
+
+    /// Whether both stamps hold the same event tree allocation, as they do
+    /// after a `fork` or `peek` until either records an event.
+    pub fn shares_events_with(&self, other: &SharedStamp<N>) -> bool {
+        Arc::ptr_eq(&self.e, &other.e)
+    }
+
+    /// Checks that both trees are in normal form and within `MAX_DEPTH`.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.validate_within(MAX_DEPTH)
+    }
+
+    /// Like `validate`, with `max_depth` in place of `MAX_DEPTH`.
+    pub fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+        validate_id(&self.i, 0, max_depth)?;
+        validate_event(&self.e, 0, max_depth)
+    }
+
+    /// Like `join`, but fails instead of panicking on invalid or overlapping
+    /// stamps, or stamps from different epochs.
+    pub fn try_join(&self, other: &SharedStamp<N>) -> Result<SharedStamp<N>, Error> {
+        self.validate()?;
+        other.validate()?;
+        if self.epoch != other.epoch {
+            return Err(Error::EpochMismatch);
+        }
+        let sum_i = sum(&self.i, &other.i)?;
+        let join_e = join(&self.e, N::ZERO, &other.e, N::ZERO, OverflowPolicy::Error)?;
+        Ok(self.with_parts(sum_i, join_e))
+    }
+
+    /// Like `event`, but fails instead of panicking on an invalid or anonymous
+    /// stamp, or when the counter would overflow.
+    pub fn try_event(&self) -> Result<SharedStamp<N>, Error> {
+        self.validate()?;
+        self.event_with(OverflowPolicy::Error)
+    }
+
+    /// Like `event`, but applies `policy` if the counter overflows.
+    pub fn event_with(&self, policy: OverflowPolicy) -> Result<SharedStamp<N>, Error> {
+        self.event_with_strategy(&MinimalGrowth, policy)
+    }
+
+    /// Like `event_with`, but lets `strategy` pick which subtree to grow
+    /// when no counter can be filled instead.
+    pub fn event_with_strategy(
+        &self,
+        strategy: &dyn GrowStrategy,
+        policy: OverflowPolicy,
+    ) -> Result<SharedStamp<N>, Error> {
+        if self.is_anonymous() {
+            return Err(Error::AnonymousStamp);
+        }
+
+        let filled_e = fill(&self.i, &self.e, policy)?;
+        let e = if filled_e != self.e {
+            filled_e
+        } else {
+            grow(&self.i, &self.e, strategy, policy)?
+        };
+
+        Ok(self.with_parts(self.i.clone(), e))
+    }
+}
+
+/// Stamps from different epochs are never ordered.
+impl<N: Counter> LessThanOrEqual for SharedStamp<N> {
+    fn leq(&self, other: &SharedStamp<N>) -> bool {
+        self.epoch == other.epoch && compare(&self.e, 0, &other.e, 0).0
+    }
+}
+
+impl<N: Counter> IntervalTreeClock for SharedStamp<N> {
+    fn peek(&self) -> (SharedStamp<N>, SharedStamp<N>) {
+        let s1 = self.with_parts(Arc::new(IdNode::Leaf(false)), self.e.clone());
+        (s1, self.clone())
+    }
+
+    /// Panics if the stamp is anonymous.
+    fn fork(&self) -> (SharedStamp<N>, SharedStamp<N>) {
+        if self.is_anonymous() {
+            panic!("{}", Error::AnonymousStamp);
+        }
+        let (i1, i2) = split(&self.i);
+        let s1 = self.with_parts(i1, self.e.clone());
+        let s2 = self.with_parts(i2, self.e.clone());
+        (s1, s2)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the ids overlap, the stamps are from different epochs or a
+    /// counter overflows. Use `try_join` for stamps that may not be
+    /// disjoint.
+    fn join(&self, other: &SharedStamp<N>) -> SharedStamp<N> {
+        if self.epoch != other.epoch {
+            panic!("{}", Error::EpochMismatch);
+        }
+        let joined = sum(&self.i, &other.i).and_then(|sum_i| {
+            let join_e = join(&self.e, N::ZERO, &other.e, N::ZERO, OverflowPolicy::Error)?;
+            Ok(self.with_parts(sum_i, join_e))
+        });
+        match joined {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Panics if the stamp is anonymous or the counter overflows; see `try_event`.
+    fn event(&self) -> SharedStamp<N> {
+        match self.event_with(OverflowPolicy::Error) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn send(&self) -> (SharedStamp<N>, SharedStamp<N>) {
+        self.event().peek()
+    }
+
+    fn receive(&self, other: &SharedStamp<N>) -> SharedStamp<N> {
+        self.join(other).event()
+    }
+
+    fn sync(&self, other: &SharedStamp<N>) -> (SharedStamp<N>, SharedStamp<N>) {
+        self.join(other).fork()
+    }
+}
+
+impl<N: Counter> From<&Stamp<N>> for SharedStamp<N> {
+    fn from(s: &Stamp<N>) -> SharedStamp<N> {
+        fn id(i: &IdTree) -> Arc<IdNode> {
+            Arc::new(match *i {
+                IdTree::Leaf { i } => IdNode::Leaf(i),
+                IdTree::Node {
+                    ref left,
+                    ref right,
+                } => IdNode::Node(id(left), id(right)),
+            })
+        }
+
+        fn event<N: Counter>(e: &EventTree<N>) -> Arc<EventNode<N>> {
+            Arc::new(match *e {
+                EventTree::Leaf { n } => EventNode::Leaf(n),
+                EventTree::Node {
+                    n,
+                    ref left,
+                    ref right,
+                } => EventNode::Node(n, event(left), event(right)),
+            })
+        }
+
+        SharedStamp {
+            i: id(&s.i),
+            e: event(&s.e),
+            epoch: s.epoch,
+        }
+    }
+}
+
+impl<N: Counter> From<&SharedStamp<N>> for Stamp<N> {
+    fn from(s: &SharedStamp<N>) -> Stamp<N> {
+        fn id(i: &IdNode) -> IdTree {
+            match *i {
+                IdNode::Leaf(i) => IdTree::leaf(i),
+                IdNode::Node(ref left, ref right) => {
+                    IdTree::node(Box::new(id(left)), Box::new(id(right)))
+                }
+            }
+        }
+
+        fn event<N: Counter>(e: &EventNode<N>) -> EventTree<N> {
+            match *e {
+                EventNode::Leaf(n) => EventTree::new_leaf(n),
+                EventNode::Node(n, ref left, ref right) => {
+                    EventTree::new_node(n, Box::new(event(left)), Box::new(event(right)))
+                }
+            }
+        }
+
+        Stamp {
+            i: id(&s.i),
+            e: event(&s.e),
+            epoch: s.epoch,
+        }
+    }
+}
+
+impl<N: Counter> EventNode<N> {
+    fn n(&self) -> N {
+        match *self {
+            EventNode::Leaf(n) => n,
+            EventNode::Node(n, _, _) => n,
+        }
+    }
+}
+
+fn zero_id() -> Arc<IdNode> {
+    Arc::new(IdNode::Leaf(false))
+}
+
+fn zero_event<N: Counter>() -> Arc<EventNode<N>> {
+    Arc::new(EventNode::Leaf(N::ZERO))
+}
+
+// e with its root counter raised by m, sharing its children
+fn lift<N: Counter>(
+    e: &Arc<EventNode<N>>,
+    m: N,
+    policy: OverflowPolicy,
+) -> Result<Arc<EventNode<N>>, Error> {
+    if m == N::ZERO {
+        return Ok(e.clone());
+    }
+    Ok(Arc::new(match **e {
+        EventNode::Leaf(n) => EventNode::Leaf(policy.add(n, m)?),
+        EventNode::Node(n, ref left, ref right) => {
+            EventNode::Node(policy.add(n, m)?, left.clone(), right.clone())
+        }
+    }))
+}
+
+// e with its root counter lowered by m, sharing its children
+fn sink<N: Counter>(e: &Arc<EventNode<N>>, m: N) -> Arc<EventNode<N>> {
+    if m == N::ZERO {
+        return e.clone();
+    }
+    Arc::new(match **e {
+        EventNode::Leaf(n) => EventNode::Leaf(n - m),
+        EventNode::Node(n, ref left, ref right) => {
+            EventNode::Node(n - m, left.clone(), right.clone())
+        }
+    })
+}
+
+// a node whose children are already normalised
+fn norm_id(left: Arc<IdNode>, right: Arc<IdNode>) -> Arc<IdNode> {
+    match (&*left, &*right) {
+        (IdNode::Leaf(i1), IdNode::Leaf(i2)) if i1 == i2 => left,
+        _ => Arc::new(IdNode::Node(left, right)),
+    }
+}
+
+// a node whose children are already normalised
+fn norm_event<N: Counter>(
+    n: N,
+    left: Arc<EventNode<N>>,
+    right: Arc<EventNode<N>>,
+    policy: OverflowPolicy,
+) -> Result<Arc<EventNode<N>>, Error> {
+    if let (EventNode::Leaf(m1), EventNode::Leaf(m2)) = (&*left, &*right) {
+        if m1 == m2 {
+            return Ok(Arc::new(EventNode::Leaf(policy.add(n, *m1)?)));
+        }
+    }
+
+    let m = cmp::min(left.n(), right.n());
+    Ok(Arc::new(EventNode::Node(
+        policy.add(n, m)?,
+        sink(&left, m),
+        sink(&right, m),
+    )))
+}
+
+fn split(i: &Arc<IdNode>) -> (Arc<IdNode>, Arc<IdNode>) {
+    match **i {
+        IdNode::Leaf(false) => (i.clone(), i.clone()),
+        IdNode::Leaf(true) => (
+            Arc::new(IdNode::Node(i.clone(), zero_id())),
+            Arc::new(IdNode::Node(zero_id(), i.clone())),
+        ),
+        IdNode::Node(ref left, ref right) => {
+            if **left == IdNode::Leaf(false) {
+                let (i1, i2) = split(right);
+                (
+                    Arc::new(IdNode::Node(left.clone(), i1)),
+                    Arc::new(IdNode::Node(left.clone(), i2)),
+                )
+            } else if **right == IdNode::Leaf(false) {
+                let (i1, i2) = split(left);
+                (
+                    Arc::new(IdNode::Node(i1, right.clone())),
+                    Arc::new(IdNode::Node(i2, right.clone())),
+                )
+            } else {
+                (
+                    Arc::new(IdNode::Node(left.clone(), zero_id())),
+                    Arc::new(IdNode::Node(zero_id(), right.clone())),
+                )
+            }
+        }
+    }
+}
+
+fn sum(a: &Arc<IdNode>, b: &Arc<IdNode>) -> Result<Arc<IdNode>, Error> {
+    match (&**a, &**b) {
+        (IdNode::Leaf(false), _) => Ok(b.clone()),
+        (_, IdNode::Leaf(false)) => Ok(a.clone()),
+        (IdNode::Node(left1, right1), IdNode::Node(left2, right2)) => {
+            Ok(norm_id(sum(left1, left2)?, sum(right1, right2)?))
+        }
+        _ => Err(Error::IdOverlap),
+    }
+}
+
+// joins a lifted by da with b lifted by db
+fn join<N: Counter>(
+    a: &Arc<EventNode<N>>,
+    da: N,
+    b: &Arc<EventNode<N>>,
+    db: N,
+    policy: OverflowPolicy,
+) -> Result<Arc<EventNode<N>>, Error> {
+    let va = policy.add(a.n(), da)?;
+    let vb = policy.add(b.n(), db)?;
+
+    if Arc::ptr_eq(a, b) {
+        return if va >= vb {
+            lift(a, da, policy)
+        } else {
+            lift(b, db, policy)
+        };
+    }
+
+    match (&**a, &**b) {
+        (EventNode::Leaf(_), EventNode::Leaf(_)) => Ok(Arc::new(EventNode::Leaf(cmp::max(va, vb)))),
+        // a normalised node is nowhere below its root, so it covers a lower leaf
+        (EventNode::Leaf(_), EventNode::Node(..)) if va <= vb => lift(b, db, policy),
+        (EventNode::Node(..), EventNode::Leaf(_)) if vb <= va => lift(a, da, policy),
+        _ => {
+            let zero = zero_event();
+            let (left1, right1) = children(a, &zero);
+            let (left2, right2) = children(b, &zero);
+            let n = cmp::min(va, vb);
+            let left = join(left1, va - n, left2, vb - n, policy)?;
+            let right = join(right1, va - n, right2, vb - n, policy)?;
+            norm_event(n, left, right, policy)
+        }
+    }
+}
+
+// a node's children, or two zero leaves for a leaf
+fn children<'a, N>(
+    e: &'a Arc<EventNode<N>>,
+    zero: &'a Arc<EventNode<N>>,
+) -> (&'a Arc<EventNode<N>>, &'a Arc<EventNode<N>>) {
+    match **e {
+        EventNode::Leaf(_) => (zero, zero),
+        EventNode::Node(_, ref left, ref right) => (left, right),
+    }
+}
+
+// returns (a <= b, b <= a) where each tree is lifted by its base
+fn compare<N: Counter>(
+    a: &Arc<EventNode<N>>,
+    base_a: u128,
+    b: &Arc<EventNode<N>>,
+    base_b: u128,
+) -> (bool, bool) {
+    let va = lift_wide(base_a, a.n());
+    let vb = lift_wide(base_b, b.n());
+    let mut leq = va <= vb;
+    let mut geq = vb <= va;
+
+    if Arc::ptr_eq(a, b) {
+        return (leq, geq);
+    }
+    if let (EventNode::Leaf(_), EventNode::Leaf(_)) = (&**a, &**b) {
+        return (leq, geq);
+    }
+
+    let zero = zero_event();
+    let (left_a, right_a) = children(a, &zero);
+    let (left_b, right_b) = children(b, &zero);
+
+    for (ca, cb) in [(left_a, left_b), (right_a, right_b)] {
+        if !leq && !geq {
+            break;
+        }
+        let (l, g) = compare(ca, va, cb, vb);
+        leq &= l;
+        geq &= g;
+    }
+
+    (leq, geq)
+}
+
+fn max<N: Counter>(e: &EventNode<N>, policy: OverflowPolicy) -> Result<N, Error> {
+    match *e {
+        EventNode::Leaf(n) => Ok(n),
+        EventNode::Node(n, ref left, ref right) => {
+            policy.add(n, cmp::max(max(left, policy)?, max(right, policy)?))
+        }
+    }
+}
+
+fn fill<N: Counter>(
+    i: &Arc<IdNode>,
+    e: &Arc<EventNode<N>>,
+    policy: OverflowPolicy,
+) -> Result<Arc<EventNode<N>>, Error> {
+    match (&**i, &**e) {
+        (IdNode::Leaf(false), _) | (_, EventNode::Leaf(_)) => Ok(e.clone()),
+        (IdNode::Leaf(true), _) => Ok(Arc::new(EventNode::Leaf(max(e, policy)?))),
+        (IdNode::Node(i_left, i_right), EventNode::Node(n, e_left, e_right)) => {
+            let (left, right) = if **i_left == IdNode::Leaf(true) {
+                let right = fill(i_right, e_right, policy)?;
+                let m = cmp::max(max(e_left, policy)?, right.n());
+                (Arc::new(EventNode::Leaf(m)), right)
+            } else if **i_right == IdNode::Leaf(true) {
+                let left = fill(i_left, e_left, policy)?;
+                let m = cmp::max(max(e_right, policy)?, left.n());
+                (left, Arc::new(EventNode::Leaf(m)))
+            } else {
+                (
+                    fill(i_left, e_left, policy)?,
+                    fill(i_right, e_right, policy)?,
+                )
+            };
+
+            if same(&left, e_left) && same(&right, e_right) {
+                Ok(e.clone())
+            } else {
+                norm_event(*n, left, right, policy)
+            }
+        }
+    }
+}
+
+// cheaper than ==, which compares unshared subtrees in full
+fn same<N: Counter>(a: &Arc<EventNode<N>>, b: &Arc<EventNode<N>>) -> bool {
+    match (&**a, &**b) {
+        (EventNode::Leaf(n1), EventNode::Leaf(n2)) => n1 == n2,
+        _ => Arc::ptr_eq(a, b),
+    }
+}
+
+// grows e within i along the sides strategy picks
+fn grow<N: Counter>(
+    i: &Arc<IdNode>,
+    e: &Arc<EventNode<N>>,
+    strategy: &dyn GrowStrategy,
+    policy: OverflowPolicy,
+) -> Result<Arc<EventNode<N>>, Error> {
+    let mut plan = Vec::new();
+    grow_plan(i, e, strategy, &mut plan)?;
+    // a saturated counter does not grow, and undoing the splits above it
+    // leaves the tree as it was
+    Ok(grow_into(i, e, N::ZERO, &plan, 0, policy)?.unwrap_or_else(|| e.clone()))
+}
+
+// summarises growing each subtree in one bottom-up pass. each node of i
+// gets a (grow left, plan index of its right child) entry in preorder.
+fn grow_plan<N: Counter>(
+    i: &IdNode,
+    e: &EventNode<N>,
+    strategy: &dyn GrowStrategy,
+    plan: &mut Vec<(bool, usize)>,
+) -> Result<GrowSide, Error> {
+    let k = plan.len();
+    plan.push((false, 0));
+    let zero = EventNode::Leaf(N::ZERO);
+    let (i_left, i_right) = match *i {
+        IdNode::Leaf(false) => return Ok(GrowSide::unowned(event_depth(e))),
+        IdNode::Leaf(true) => return Ok(GrowSide::owned_leaf()),
+        IdNode::Node(ref left, ref right) => (left, right),
+    };
+    let (e_left, e_right, split) = match *e {
+        EventNode::Leaf(_) => (&zero, &zero, true),
+        EventNode::Node(_, ref left, ref right) => (&**left, &**right, false),
+    };
+
+    let left = grow_plan(i_left, e_left, strategy, plan)?;
+    let right_k = plan.len();
+    let right = grow_plan(i_right, e_right, strategy, plan)?;
+    let (side, grow_left) = GrowSide::node(strategy, left, right, split)?;
+    plan[k] = (grow_left, right_k);
+    Ok(side)
+}
+
+fn event_depth<N: Counter>(e: &EventNode<N>) -> usize {
+    match *e {
+        EventNode::Leaf(_) => 0,
+        EventNode::Node(_, ref left, ref right) => {
+            1 + cmp::max(event_depth(left), event_depth(right))
+        }
+    }
+}
+
+// grows along the sides in plan from entry k, copying only the path, with
+// counters below e lifted by base. None if the grown counter was saturated.
+fn grow_into<N: Counter>(
+    i: &Arc<IdNode>,
+    e: &Arc<EventNode<N>>,
+    base: N,
+    plan: &[(bool, usize)],
+    k: usize,
+    policy: OverflowPolicy,
+) -> Result<Option<Arc<EventNode<N>>>, Error> {
+    let (n, e_left, e_right) = match **e {
+        EventNode::Leaf(n) if **i == IdNode::Leaf(true) => {
+            let value = policy.add(base, n)?;
+            let step = policy.add(value, N::ONE)? - value;
+            if step == N::ZERO {
+                return Ok(None);
+            }
+            return Ok(Some(Arc::new(EventNode::Leaf(n + step))));
+        }
+        EventNode::Leaf(n) => (n, zero_event(), zero_event()),
+        EventNode::Node(n, ref left, ref right) => (n, left.clone(), right.clone()),
+    };
+    let (i_left, i_right) = match **i {
+        IdNode::Node(ref left, ref right) => (left, right),
+        // grow_plan only picks sides the id owns part of
+        IdNode::Leaf(_) => return Err(Error::NonNormalIdTree),
+    };
+
+    let base = policy.add(base, n)?;
+    let (grow_left, right_k) = plan[k];
+    Ok(if grow_left {
+        grow_into(i_left, &e_left, base, plan, k + 1, policy)?
+            .map(|left| Arc::new(EventNode::Node(n, left, e_right)))
+    } else {
+        grow_into(i_right, &e_right, base, plan, right_k, policy)?
+            .map(|right| Arc::new(EventNode::Node(n, e_left, right)))
+    })
+}
+
+// checks i in preorder, as `IdTree::validate_within` does
+fn validate_id(i: &IdNode, depth: usize, max_depth: usize) -> Result<(), Error> {
+    if depth > max_depth {
+        return Err(Error::DepthLimitExceeded);
+    }
+    if let IdNode::Node(ref left, ref right) = *i {
+        if let (IdNode::Leaf(i1), IdNode::Leaf(i2)) = (&**left, &**right) {
+            if i1 == i2 {
+                return Err(Error::NonNormalIdTree);
+            }
+        }
+        validate_id(left, depth + 1, max_depth)?;
+        validate_id(right, depth + 1, max_depth)?;
+    }
+    Ok(())
+}
+
+// checks e in preorder, as `EventTree::validate_within` does
+fn validate_event<N: Counter>(
+    e: &EventNode<N>,
+    depth: usize,
+    max_depth: usize,
+) -> Result<(), Error> {
+    if depth > max_depth {
+        return Err(Error::DepthLimitExceeded);
+    }
+    if let EventNode::Node(_, ref left, ref right) = *e {
+        if let (EventNode::Leaf(n1), EventNode::Leaf(n2)) = (&**left, &**right) {
+            if n1 == n2 {
+                return Err(Error::NonNormalEventTree);
+            }
+        }
+        if cmp::min(left.n(), right.n()) != N::ZERO {
+            return Err(Error::NonNormalEventTree);
+        }
+        validate_event(left, depth + 1, max_depth)?;
+        validate_event(right, depth + 1, max_depth)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::{LargestRegion, ShallowGrowth};
+    use crate::tests::*;
+
+    impl Representation for SharedStamp {
+        fn seed() -> SharedStamp {
+            SharedStamp::seed()
+        }
+
+        fn causality(&self, other: &SharedStamp) -> Causality {
+            SharedStamp::causality(self, other)
+        }
+
+        fn epoch(&self) -> u32 {
+            SharedStamp::epoch(self)
+        }
+
+        fn validate(&self) -> Result<(), Error> {
+            SharedStamp::validate(self)
+        }
+
+        fn validate_within(&self, max_depth: usize) -> Result<(), Error> {
+            SharedStamp::validate_within(self, max_depth)
+        }
+
+        fn try_join(&self, other: &SharedStamp) -> Result<SharedStamp, Error> {
+            SharedStamp::try_join(self, other)
+        }
+
+        fn try_event(&self) -> Result<SharedStamp, Error> {
+            SharedStamp::try_event(self)
+        }
+
+        fn event_with(&self, policy: OverflowPolicy) -> Result<SharedStamp, Error> {
+            SharedStamp::event_with(self, policy)
+        }
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedStamp>();
+        assert_send_sync::<SharedStamp<u64>>();
+    }
+
+    #[test]
+    fn conversion_round_trip() {
+        check_round_trip::<SharedStamp>();
+    }
+
+    #[test]
+    fn keeps_epoch() {
+        check_keeps_epoch::<SharedStamp>();
+    }
+
+    #[test]
+    #[should_panic(expected = "stamps are from different epochs")]
+    fn join_across_epochs_panics() {
+        join_across_epochs::<SharedStamp>();
+    }
+
+    #[test]
+    fn compare_near_counter_max() {
+        check_compare_near_counter_max::<SharedStamp>();
+    }
+
+    #[test]
+    fn fallible_verbs_match_boxed() {
+        check_fallible_verbs::<SharedStamp>();
+    }
+
+    #[test]
+    fn validate_matches_boxed() {
+        check_validate::<SharedStamp>();
+    }
+
+    #[test]
+    fn fork_and_peek_share_events() {
+        let s = SharedStamp::seed().event().fork().0.event();
+        let (a, b) = s.fork();
+        assert!(a.shares_events_with(&s));
+        assert!(b.shares_events_with(&s));
+
+        let (msg, same) = a.peek();
+        assert!(msg.shares_events_with(&a));
+        assert!(Arc::ptr_eq(&same.i, &a.i));
+        assert!(msg.is_anonymous());
+
+        // a owns ((1,0),0), so its event grows the left subtree and shares the right
+        let a = a.event();
+        assert!(!a.shares_events_with(&b));
+        if let (EventNode::Node(_, l1, r1), EventNode::Node(_, l2, r2)) = (&*a.e, &*b.e) {
+            assert!(!Arc::ptr_eq(l1, l2));
+            assert!(Arc::ptr_eq(r1, r2));
+        } else {
+            panic!("expected node event trees");
+        }
+    }
+
+    #[test]
+    fn matches_boxed_random() {
+        check_matches_boxed_random::<SharedStamp>(11);
+    }
+
+    #[test]
+    fn strategies_match_boxed() {
+        let strategies: [&dyn GrowStrategy; 3] = [&MinimalGrowth, &ShallowGrowth, &LargestRegion];
+        for strategy in strategies {
+            let mut rng = Lcg::new(5);
+            let mut boxed = Stamp::seed().fork_n(4);
+            let mut shared: Vec<SharedStamp> = boxed.iter().map(SharedStamp::from).collect();
+            for _ in 0..60 {
+                let k = rng.next(boxed.len());
+                boxed[k] = boxed[k]
+                    .event_with_strategy(strategy, OverflowPolicy::Error)
+                    .unwrap();
+                shared[k] = shared[k]
+                    .event_with_strategy(strategy, OverflowPolicy::Error)
+                    .unwrap();
+                check_converts(&boxed[k], &shared[k]);
+
+                let j = rng.next(boxed.len());
+                boxed[k] = boxed[k].join(&boxed[j].peek().0);
+                shared[k] = shared[k].join(&shared[j].peek().0);
+            }
+        }
+    }
+}